@@ -0,0 +1,120 @@
+use super::*;
+
+#[test]
+fn balances_show_all_decimals() {
+    assert_eq!(format!("{:#}", Balance::new(123, 10)), "0.0000000123");
+    assert_eq!(
+        format!("{:#}", Balance::new(12345678905, 10)),
+        "1.2345678905"
+    );
+    assert_eq!(
+        format!("{:#}", Balance::new(1234567890, 10)),
+        "0.1234567890"
+    );
+    assert_eq!(format!("{}", Balance::new(0, 0)), "0");
+}
+
+#[test]
+fn balances_show_thousands_separators_and_symbol() {
+    let balance = Balance::new(12_345_678_900_000_000, 10);
+    assert_eq!(balance.to_string(), "1,234,567.8900000000");
+    assert_eq!(
+        balance.with_symbol("DOT").to_string(),
+        "1,234,567.8900000000 DOT"
+    );
+    assert_eq!(
+        format!("{:#}", balance.with_symbol("DOT")),
+        "1234567.8900000000 DOT"
+    );
+    assert_eq!(Balance::new(123_456, 0).to_string(), "123,456");
+    assert_eq!(
+        Balance::new(u128::MAX, 18).to_string(),
+        "340,282,366,920,938,463,463.374607431768211455"
+    );
+}
+
+#[test]
+fn balances_parse_exactly() -> Result<(), ScError> {
+    assert_eq!(
+        Balance::parse("1.5", 10, Rounding::Exact)?,
+        Balance::new(15_000_000_000, 10)
+    );
+    assert_eq!(
+        Balance::parse("1,234,567.89", 10, Rounding::Exact)?,
+        Balance::new(12_345_678_900_000_000, 10)
+    );
+    assert_eq!(Balance::parse(".25", 2, Rounding::Exact)?.planck, 25);
+    assert_eq!(Balance::parse("7", 0, Rounding::Exact)?.planck, 7);
+    // Every balance parses back from how it is shown
+    let balance = Balance::new(u128::MAX, 18);
+    assert_eq!(
+        Balance::parse(&balance.to_string(), 18, Rounding::Exact)?,
+        balance
+    );
+
+    for invalid in ["", ".", "1.2.3", "-1", "1e10", "DOT"] {
+        assert!(matches!(
+            Balance::parse(invalid, 10, Rounding::Exact),
+            Err(ScError::InvalidBalance(_))
+        ));
+    }
+    assert!(Balance::parse(
+        "340282366920938463463374607431768211456",
+        0,
+        Rounding::Exact
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn balances_round_as_asked() -> Result<(), ScError> {
+    let parse = |s, rounding| Balance::parse(s, 2, rounding).map(|b| b.planck);
+    assert_eq!(parse("1.234", Rounding::Down)?, 123);
+    assert_eq!(parse("1.234", Rounding::Up)?, 124);
+    assert_eq!(parse("1.234", Rounding::Nearest)?, 123);
+    assert_eq!(parse("1.235", Rounding::Nearest)?, 124);
+    assert_eq!(parse("1.230", Rounding::Exact)?, 123);
+    assert!(parse("1.234", Rounding::Exact).is_err());
+
+    let balance = Balance::new(10, 2);
+    assert_eq!(balance.checked_div(4, Rounding::Down).unwrap().planck, 2);
+    assert_eq!(balance.checked_div(4, Rounding::Up).unwrap().planck, 3);
+    assert_eq!(balance.checked_div(4, Rounding::Nearest).unwrap().planck, 3);
+    assert_eq!(balance.checked_div(3, Rounding::Nearest).unwrap().planck, 3);
+    assert_eq!(balance.checked_div(5, Rounding::Exact).unwrap().planck, 2);
+    assert_eq!(balance.checked_div(4, Rounding::Exact), None);
+    assert_eq!(balance.checked_div(0, Rounding::Down), None);
+    Ok(())
+}
+
+#[test]
+fn balance_arithmetic_is_checked() {
+    let one = Balance::new(10_000_000_000, 10);
+    assert_eq!(one.checked_add(one), Some(Balance::new(20_000_000_000, 10)));
+    assert_eq!(one.checked_sub(one.checked_mul(2).unwrap()), None);
+    assert_eq!(Balance::new(u128::MAX, 10).checked_add(one), None);
+    // Planck of tokens with different decimals don't add up
+    assert_eq!(one.checked_add(Balance::from(1)), None);
+    assert_eq!(
+        Balance::checked_sum(vec![one, one, one], 10),
+        Some(Balance::new(30_000_000_000, 10))
+    );
+    assert_eq!(Balance::checked_sum(vec![one, Balance::from(1)], 10), None);
+    assert_eq!(Balance::new(15_000_000_000, 10).to_f64(), 1.5);
+}
+
+#[test]
+fn balances_serialize_and_encode_as_planck() -> Result<(), Box<dyn std::error::Error>> {
+    let balance = Balance::new(u128::MAX, 10);
+    assert_eq!(
+        serde_json::to_string(&balance)?,
+        "\"340282366920938463463374607431768211455\""
+    );
+    let decoded = Balance::decode(&mut balance.encode().as_slice())?;
+    assert_eq!(decoded, Balance::from(u128::MAX));
+    assert_eq!(balance.encode(), u128::MAX.encode());
+    let deserialized: Balance = serde_json::from_str("\"12345\"")?;
+    assert_eq!(deserialized, Balance::from(12345));
+    Ok(())
+}
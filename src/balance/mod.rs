@@ -0,0 +1,241 @@
+#[cfg(test)]
+mod balance_tests;
+
+use std::fmt;
+
+use parity_scale_codec::{Decode, Encode, Input, Output};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{util, ScError, TokenDecimals};
+
+/// How to round amounts that have more decimals than their token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// Towards zero
+    #[default]
+    Down,
+    /// Away from zero
+    Up,
+    /// To the nearest planck, halves away from zero
+    Nearest,
+    /// Refuse to round
+    Exact,
+}
+
+impl Rounding {
+    /// Whether to add one planck, given the first dropped digit and whether any dropped digit is non-zero.
+    /// Returns None if an exact amount was asked for, but digits would be lost.
+    fn round_up(&self, first_dropped: u8, any_dropped: bool) -> Option<bool> {
+        match self {
+            Rounding::Down => Some(false),
+            Rounding::Up => Some(any_dropped),
+            Rounding::Nearest => Some(first_dropped >= 5),
+            Rounding::Exact if any_dropped => None,
+            Rounding::Exact => Some(false),
+        }
+    }
+}
+
+/// An exact amount of a token, counted in planck, its smallest unit.
+/// One token is 10^decimals planck.
+///
+/// Balances decoded from the chain, or read from an indexer or a store,
+/// don't know their token, and come with zero decimals.
+/// Give them the chain's decimals with [`Balance::with_decimals`] before showing them as tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Balance {
+    pub planck: u128,
+    pub decimals: TokenDecimals,
+}
+
+impl From<u128> for Balance {
+    fn from(planck: u128) -> Self {
+        Balance {
+            planck,
+            decimals: 0,
+        }
+    }
+}
+
+impl Balance {
+    pub fn new(planck: u128, decimals: TokenDecimals) -> Self {
+        Balance { planck, decimals }
+    }
+
+    /// The same planck, as an amount of a token with these decimals
+    pub fn with_decimals(self, decimals: TokenDecimals) -> Self {
+        Balance { decimals, ..self }
+    }
+
+    /// Parses an amount of tokens, like "1.5" or "1,000.25", into planck.
+    /// Digits beyond the token's decimals are rounded as asked.
+    pub fn parse(s: &str, decimals: TokenDecimals, rounding: Rounding) -> Result<Self, ScError> {
+        let invalid = || ScError::InvalidBalance(s.into());
+        let digits: String = s.trim().chars().filter(|c| *c != ',').collect();
+        let (whole, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+        if (whole.is_empty() && fraction.is_empty())
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let (kept, dropped) = fraction.split_at(fraction.len().min(decimals));
+        let first_dropped = dropped.bytes().next().map_or(0, |b| b - b'0');
+        let any_dropped = dropped.bytes().any(|b| b != b'0');
+        let round_up = rounding
+            .round_up(first_dropped, any_dropped)
+            .ok_or_else(invalid)?;
+
+        // Whole and kept digits, padded to the token's decimals, are the planck
+        let padding = "0".repeat(decimals - kept.len());
+        let planck_digits = format!("{whole}{kept}{padding}");
+        let planck_digits = planck_digits.trim_start_matches('0');
+        let planck = match planck_digits {
+            "" => 0,
+            planck_digits => planck_digits.parse::<u128>().map_err(|_| invalid())?,
+        };
+        let planck = planck.checked_add(round_up as u128).ok_or_else(invalid)?;
+        Ok(Balance { planck, decimals })
+    }
+
+    /// None if the sum overflows, or if the balances are of tokens with different decimals
+    pub fn checked_add(self, other: Balance) -> Option<Self> {
+        if self.decimals != other.decimals {
+            return None;
+        }
+        Some(Balance {
+            planck: self.planck.checked_add(other.planck)?,
+            ..self
+        })
+    }
+
+    /// None if the difference is negative, or if the balances are of tokens with different decimals
+    pub fn checked_sub(self, other: Balance) -> Option<Self> {
+        if self.decimals != other.decimals {
+            return None;
+        }
+        Some(Balance {
+            planck: self.planck.checked_sub(other.planck)?,
+            ..self
+        })
+    }
+
+    pub fn checked_mul(self, factor: u128) -> Option<Self> {
+        Some(Balance {
+            planck: self.planck.checked_mul(factor)?,
+            ..self
+        })
+    }
+
+    /// None if the divisor is zero, or if an exact quotient was asked for but there is a remainder
+    pub fn checked_div(self, divisor: u128, rounding: Rounding) -> Option<Self> {
+        let quotient = self.planck.checked_div(divisor)?;
+        let remainder = self.planck % divisor;
+        // Twice the remainder is compared with the divisor, without overflowing
+        let half_or_more = remainder >= divisor - remainder;
+        let round_up = match rounding {
+            Rounding::Nearest => half_or_more,
+            rounding => rounding.round_up(0, remainder != 0)?,
+        };
+        Some(Balance {
+            planck: quotient + round_up as u128,
+            ..self
+        })
+    }
+
+    /// Sums balances of a token with these decimals.
+    /// None on overflow, or if some balance is of a token with other decimals.
+    pub fn checked_sum(
+        balances: impl IntoIterator<Item = Balance>,
+        decimals: TokenDecimals,
+    ) -> Option<Self> {
+        balances
+            .into_iter()
+            .try_fold(Balance::new(0, decimals), Balance::checked_add)
+    }
+
+    /// The amount in tokens, for plots and other places where precision doesn't matter
+    pub fn to_f64(&self) -> f64 {
+        // Parsing the exact decimal string rounds only once
+        format!("{self:#}").parse().unwrap_or(f64::NAN)
+    }
+
+    /// Shows the balance followed by a token symbol, like "1,000.5 DOT"
+    pub fn with_symbol<'a>(&self, symbol: &'a str) -> WithSymbol<'a> {
+        WithSymbol {
+            balance: *self,
+            symbol,
+        }
+    }
+}
+
+/// Shows the amount in tokens, with all of the token's decimals,
+/// like "1,000.5000000000". The alternate form `{:#}` leaves out thousands separators.
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!("{:0>width$}", self.planck, width = self.decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - self.decimals);
+        if f.alternate() {
+            write!(f, "{whole}")?;
+        } else {
+            for (i, digit) in whole.chars().enumerate() {
+                if i > 0 && (whole.len() - i) % 3 == 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, "{digit}")?;
+            }
+        }
+        if !fraction.is_empty() {
+            write!(f, ".{fraction}")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct WithSymbol<'a> {
+    balance: Balance,
+    symbol: &'a str,
+}
+
+impl fmt::Display for WithSymbol<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{:#} {}", self.balance, self.symbol)
+        } else {
+            write!(f, "{} {}", self.balance, self.symbol)
+        }
+    }
+}
+
+/// Balances are serialized as their planck in a string,
+/// since json numbers lose precision beyond 2^53
+impl Serialize for Balance {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.planck)
+    }
+}
+
+impl<'de> Deserialize<'de> for Balance {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        util::balance_from_maybe_str(deserializer).map(Balance::from)
+    }
+}
+
+/// Balances are encoded like the chain encodes them, as u128 planck
+impl Encode for Balance {
+    fn size_hint(&self) -> usize {
+        self.planck.size_hint()
+    }
+
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        self.planck.encode_to(dest)
+    }
+}
+
+impl Decode for Balance {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+        Ok(u128::decode(input)?.into())
+    }
+}
@@ -1,20 +1,275 @@
+use chrono::{NaiveDateTime, TimeZone};
+use clap::{Arg, ArgMatches, Command};
+use ndarray::Array;
 use poloto::num::timestamp::UnixTime;
 use poloto::prelude::*;
 use stake_checker::*;
 use std::cmp::min;
-use ndarray::Array;
+use std::fs;
+
+/// The profile's plot settings, with those given on the command line instead
+fn plot_settings(matches: &ArgMatches, profile: &Profile) -> PlotSettings {
+    let mut settings = profile.plot.clone();
+    if matches.is_present("expected-apr") {
+        settings.expected_apr = matches.value_of_t_or_exit("expected-apr");
+    }
+    if matches.is_present("apr-band") {
+        settings.apr_band = matches.value_of_t_or_exit("apr-band");
+    }
+    if matches.is_present("apr-lines") {
+        settings.apr_lines = matches.value_of_t_or_exit("apr-lines");
+    }
+    if matches.is_present("sma-days") {
+        settings.sma_window_days = matches.value_of_t_or_exit("sma-days");
+    }
+    if matches.is_present("width") {
+        settings.width = matches.value_of_t_or_exit("width");
+    }
+    if matches.is_present("height") {
+        settings.height = matches.value_of_t_or_exit("height");
+    }
+    match matches.value_of("theme") {
+        Some("light") => settings.theme = Theme::Light,
+        Some("dark") => settings.theme = Theme::Dark,
+        _ => (),
+    }
+    settings
+}
+
+/// Rewards as histogram staples of uniform width,
+/// each followed by a zero an hour later
+fn histogram_data<Tz: TimeZone>(
+    rewards: &[Reward],
+    token_decimals: TokenDecimals,
+    timezone: &Tz,
+) -> Vec<(UnixTime, f64)> {
+    let mut rewards_w_dummys: Vec<Reward> = vec![];
+    for reward in rewards {
+        rewards_w_dummys.push(reward.clone());
+        rewards_w_dummys.push(Reward {
+            date: reward
+                .date
+                .checked_add_signed(chrono::Duration::hours(1))
+                .unwrap_or(reward.date),
+            balance: Balance::new(0, token_decimals),
+            id: None,
+        });
+    }
+    rewards_w_dummys
+        .iter()
+        .map(|r| {
+            let d = timezone.from_utc_datetime(&r.date);
+            (UnixTime::from(d), r.balance.to_f64())
+        })
+        .collect()
+}
+
+/// Polkadot keeps this many eras of reward history
+const CHAIN_APR_ERAS: u32 = 84;
 
-fn main() -> Result<(), ScError> {
+/// The mean realised APR of the eras that started in the plotted range,
+/// read from the chain through the profile's RPC endpoint
+async fn chain_apr(
+    profile: &Profile,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+) -> Result<f64, ScError> {
+    let rpc = RpcClient::new(&profile.rpc_endpoint()?);
+    let metadata = fetch_metadata(&rpc, None).await?;
+    let aprs: Vec<EraApr> = get_recent_era_aprs(&rpc, &metadata, CHAIN_APR_ERAS, None)
+        .await?
+        .into_iter()
+        .filter(|era_apr| {
+            era_apr.start.is_some_and(|start| {
+                from.is_none_or(|from| start >= from) && to.is_none_or(|to| start < to)
+            })
+        })
+        .collect();
+    let apr = mean_apr(&aprs).ok_or(ScError::NoDataFound)?;
+    eprintln!(
+        "Realised APR was {:.2}% over {} eras",
+        apr * 100.,
+        aprs.len()
+    );
+    Ok(apr)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ScError> {
     let timezone = &chrono::Utc;
-    use chrono::TimeZone;
 
-    let polkadot_properties_file = polkadot_properties_file_from_env();
-    let token_decimals = token_decimals(polkadot_properties_file)?;
+    let matches = Command::new("plotit")
+        .version("1.0")
+        .author("Torbjørn L. <tobben@fastmail.fm>")
+        .about(
+            "Plot known staking rewards, their moving average, \
+            and the rewards expected from the stake. \
+            Settings not given default to those in the profile's plot section.",
+        )
+        .arg(
+            Arg::with_name("account")
+                .long("account")
+                .takes_value(true)
+                .value_name("name")
+                .help("Plot one of the accounts. All accounts are plotted together if omitted."),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("file")
+                .help("Config file to read profiles from"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .value_name("name")
+                .help("Profile of the config file to use"),
+        )
+        .arg(
+            Arg::with_name("expected-apr")
+                .long("expected-apr")
+                .takes_value(true)
+                .value_name("fraction")
+                .help("Rewards expected per year, as a fraction of the stake, like 0.15"),
+        )
+        .arg(
+            Arg::with_name("chain-apr")
+                .long("chain-apr")
+                .takes_value(false)
+                .conflicts_with("expected-apr")
+                .help(
+                    "Expect the realised APR of the plotted eras, read from the chain \
+                    through RPC_ENDPOINT. Chains only keep recent eras.",
+                ),
+        )
+        .arg(
+            Arg::with_name("apr-band")
+                .long("apr-band")
+                .takes_value(true)
+                .value_name("fraction")
+                .help("Also draw expected rewards for the expected APR plus and minus this"),
+        )
+        .arg(
+            Arg::with_name("apr-lines")
+                .long("apr-lines")
+                .takes_value(true)
+                .value_name("count")
+                .help("How many lines of expected rewards to spread over the APR band"),
+        )
+        .arg(
+            Arg::with_name("sma-days")
+                .long("sma-days")
+                .takes_value(true)
+                .value_name("days")
+                .help("Days to average rewards over"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .long("width")
+                .takes_value(true)
+                .value_name("pixels")
+                .help("Width of the plot"),
+        )
+        .arg(
+            Arg::with_name("height")
+                .long("height")
+                .takes_value(true)
+                .value_name("pixels")
+                .help("Height of the plot"),
+        )
+        .arg(
+            Arg::with_name("theme")
+                .long("theme")
+                .takes_value(true)
+                .possible_values(["dark", "light"])
+                .help("Colours of the plot"),
+        )
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .value_name("date")
+                .help("Plot from this date on, like 2023-01-01 or 2023-01-01T00:00:00"),
+        )
+        .arg(
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .value_name("date")
+                .help("Plot until just before this date, like 2024-01-01"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .short('o')
+                .takes_value(true)
+                .value_name("file")
+                .help("Svg file to write the plot to. Printed if omitted."),
+        )
+        .get_matches();
+
+    let profile = load_profile(matches.value_of("config"), matches.value_of("profile"))?;
+    let mut settings = plot_settings(&matches, &profile);
+    if settings.sma_window_days == 0 {
+        return Err(ScError::InvalidConfig(
+            "plot: the moving average needs at least one day".into(),
+        ));
+    }
+    if settings.apr_lines == 0 {
+        return Err(ScError::InvalidConfig(
+            "plot: at least one line of expected rewards is needed".into(),
+        ));
+    }
+    let from = matches.value_of("from").map(parse_date).transpose()?;
+    let to = matches.value_of("to").map(parse_date).transpose()?;
+    if matches.is_present("chain-apr") {
+        settings.expected_apr = chain_apr(&profile, from, to).await?;
+    }
+    let polkadot_properties_file = profile.polkadot_properties_file();
+    let chain = ChainProfile::from_file(polkadot_properties_file)?;
+    let token_decimals = chain.token_decimals;
 
-    let known_rewards_file = known_rewards_file_from_env();
-    let rewards = known_rewards(known_rewards_file)?;
-    let known_stake_changes_file = known_stake_changes_file_from_env();
-    let stake_changes = known_stake_changes(known_stake_changes_file)?;
+    // Plot one account if named, or else all accounts together
+    let accounts = select_accounts(profile.accounts()?, matches.value_of("account"))?;
+    chain.check_accounts(&accounts)?;
+    let mut rewards_per_account = vec![];
+    let mut pool_rewards_per_account = vec![];
+    let mut stake_changes_per_account = vec![];
+    for account in &accounts {
+        let store = profile.store_for_account(account)?;
+        rewards_per_account.push(store.rewards()?);
+        pool_rewards_per_account.push(store.pool_rewards()?);
+        stake_changes_per_account.push(store.stake_changes()?);
+    }
+    // Stored amounts are planck, of the chain's token
+    let rewards: Vec<Reward> = rewards_between(aggregate_rewards(rewards_per_account), from, to)
+        .into_iter()
+        .map(|r| Reward {
+            balance: r.balance.with_decimals(token_decimals),
+            ..r
+        })
+        .collect();
+    let pool_rewards: Vec<Reward> =
+        rewards_between(aggregate_rewards(pool_rewards_per_account), from, to)
+            .into_iter()
+            .map(|r| Reward {
+                balance: r.balance.with_decimals(token_decimals),
+                ..r
+            })
+            .collect();
+    let stake_changes = aggregate_stake_changes(stake_changes_per_account);
+    let stake_changes: Vec<StakeChange> = stake_changes_between(stake_changes, from, to)
+        .into_iter()
+        .map(|c| StakeChange {
+            accumulated_amount: c.accumulated_amount.with_decimals(token_decimals),
+            ..c
+        })
+        .collect();
+    if rewards.is_empty() && pool_rewards.is_empty() {
+        return Err(ScError::NoDataFound);
+    }
 
     // Build the expected reward data set
     let mut stake_changes_w_dummys: Vec<StakeChange> = vec![];
@@ -22,7 +277,7 @@ fn main() -> Result<(), ScError> {
     // stake increases sharply
     let mut it = stake_changes.iter().peekable();
     while let Some(stake_change) = it.next() {
-        stake_changes_w_dummys.push(*stake_change);
+        stake_changes_w_dummys.push(stake_change.clone());
         stake_changes_w_dummys.push(StakeChange {
             timestamp: it
                 .peek()
@@ -31,6 +286,7 @@ fn main() -> Result<(), ScError> {
                 .checked_add_signed(chrono::Duration::hours(-1))
                 .unwrap_or(stake_change.timestamp),
             accumulated_amount: stake_change.accumulated_amount,
+            id: None,
         });
     }
     let dates_expected_rewards = stake_changes_w_dummys.iter().map(|r| {
@@ -38,79 +294,77 @@ fn main() -> Result<(), ScError> {
         UnixTime::from(d)
     });
 
-    // Shamelessly hard-coded from historical rewards rate on Dec 28, 2022
-    // https://staking.polkadot.network/#/overview
-    const EXPECTED_APR: f64 = 0.1566;
-    const RANGE_D_APR: f64 = 0.05;
-    const NUMPOINTS_APR: usize = 3;
-    const START_APR: f64 = EXPECTED_APR - RANGE_D_APR;
-    const END_APR: f64 = EXPECTED_APR + RANGE_D_APR;
-    let aprs = Array::linspace(START_APR, END_APR, NUMPOINTS_APR);
+    let aprs = match settings.apr_lines {
+        1 => Array::from_elem(1, settings.expected_apr),
+        lines => {
+            let start_apr = settings.expected_apr - settings.apr_band;
+            let end_apr = settings.expected_apr + settings.apr_band;
+            Array::linspace(start_apr, end_apr, lines)
+        }
+    };
 
-
-    let expected_rewards = aprs.iter().map(|apr|
+    let expected_rewards = aprs
+        .iter()
+        .map(|apr| {
             stake_changes_w_dummys
-            .iter()
-            .map(|c| {
-                let daily_growth_factor: f64 = apr / 365f64;
-                let dots = (c.accumulated_amount as f64) / f64::powf(10f64, token_decimals as f64);
-                dots * daily_growth_factor
-            }).collect::<Vec<_>>())
+                .iter()
+                .map(|c| {
+                    let daily_growth_factor: f64 = apr / 365f64;
+                    let dots = c.accumulated_amount.to_f64();
+                    dots * daily_growth_factor
+                })
+                .collect::<Vec<_>>()
+        })
         .collect::<Vec<_>>();
-    let data_expected_rewards: Vec<_> = expected_rewards.iter().map(|e| dates_expected_rewards.clone().zip(e)).collect();
-
+    let apr_lines: Vec<_> = expected_rewards
+        .iter()
+        .zip(aprs.iter())
+        .map(|(expected, apr)| {
+            dates_expected_rewards
+                .clone()
+                .zip(expected)
+                .buffered_plot()
+                .line(format!("{:.1}% APR", apr * 100.))
+        })
+        .collect();
 
     // Add dummy data to rewards to get uniform width histogram staples
-    let mut rewards_w_dummys: Vec<Reward> = vec![];
-    for reward in &rewards {
-        rewards_w_dummys.push(*reward);
-        rewards_w_dummys.push(Reward {
-            date: reward
-                .date
-                .checked_add_signed(chrono::Duration::hours(1))
-                .unwrap_or(reward.date),
-            balance: 0,
-        });
-    }
-    let dates_w_dummys = rewards_w_dummys.iter().map(|r| {
-        let d = timezone.from_utc_datetime(&r.date);
-        UnixTime::from(d)
-    });
-    let balances_w_dummys = rewards_w_dummys
-        .iter()
-        .map(|r| (r.balance as f64) / f64::powf(10f64, token_decimals as f64))
-        .collect::<Vec<_>>();
-    let data_w_dummys = dates_w_dummys.zip(balances_w_dummys);
+    let data_w_dummys = histogram_data(&rewards, token_decimals, timezone);
+    let pool_data_w_dummys = histogram_data(&pool_rewards, token_decimals, timezone);
 
     // Build time averaged rewards data set
     let window_step_interval = chrono::Duration::days(1);
-    let window_steps = 14;
+    let window_steps = settings.sma_window_days as i32;
     let window_length = window_step_interval * window_steps;
-    let mut window_start = rewards.first().unwrap().date;
-    let mut window_end = window_start.checked_add_signed(window_length).unwrap();
-    let mut skip_samples = 0;
     let mut rewards_time_averaged: Vec<Reward> = vec![];
-    while window_end <= rewards.last().unwrap().date {
-        window_start += window_step_interval;
-        window_end += window_step_interval;
-
-        skip_samples += rewards[skip_samples..]
-            .iter()
-            .position(|x| x.date > window_start)
-            .unwrap_or(0);
-
-        let right_pos = rewards[skip_samples..]
-            .iter()
-            .position(|x| x.date >= window_end)
-            .unwrap_or(rewards.len());
-        let sum = rewards[skip_samples..min(right_pos + skip_samples, rewards.len())]
-            .iter()
-            .fold(0, |acc, x| acc + x.balance)
-            / (window_steps as u128);
-        rewards_time_averaged.push(Reward {
-            date: window_end,
-            balance: sum,
-        });
+    // Only staking rewards are averaged, and pool members may have none
+    if let (Some(first), Some(last)) = (rewards.first(), rewards.last()) {
+        let mut window_start = first.date;
+        let mut window_end = window_start.checked_add_signed(window_length).unwrap();
+        let mut skip_samples = 0;
+        while window_end <= last.date {
+            window_start += window_step_interval;
+            window_end += window_step_interval;
+
+            skip_samples += rewards[skip_samples..]
+                .iter()
+                .position(|x| x.date > window_start)
+                .unwrap_or(0);
+
+            let right_pos = rewards[skip_samples..]
+                .iter()
+                .position(|x| x.date >= window_end)
+                .unwrap_or(rewards.len());
+            let window = &rewards[skip_samples..min(right_pos + skip_samples, rewards.len())];
+            let average = Balance::checked_sum(window.iter().map(|x| x.balance), token_decimals)
+                .and_then(|sum| sum.checked_div(window_steps as u128, Rounding::Nearest))
+                .ok_or_else(|| ScError::InvalidBalance("sum of rewards overflows".into()))?;
+            rewards_time_averaged.push(Reward {
+                date: window_end,
+                balance: average,
+                id: None,
+            });
+        }
     }
     let dates_time_averaged = rewards_time_averaged.iter().map(|r| {
         let d = timezone.from_utc_datetime(&r.date);
@@ -118,27 +372,20 @@ fn main() -> Result<(), ScError> {
     });
     let balances_time_averaged = rewards_time_averaged
         .iter()
-        .map(|r| (r.balance as f64) / f64::powf(10f64, token_decimals as f64))
+        .map(|r| r.balance.to_f64())
         .collect::<Vec<_>>();
     let data_time_averaged = dates_time_averaged.zip(balances_time_averaged);
 
     let data_for_plot = poloto::data(plots!(
         data_w_dummys.buffered_plot().histogram("Payouts"),
+        pool_data_w_dummys.buffered_plot().histogram("Pool payouts"),
         data_time_averaged
             .buffered_plot()
             .line(format!("SMA {window_steps} days")),
-        data_expected_rewards[0].clone()
-            .buffered_plot()
-            .line(format!("{:.1}% APR", aprs[0] * 100.)),
-        data_expected_rewards[1].clone()
-            .buffered_plot()
-            .line(format!("{:.1}% APR", aprs[1] * 100.)),
-        data_expected_rewards[2].clone()
-            .buffered_plot()
-            .line(format!("{:.1}% APR", aprs[2] * 100.))
+        poloto::build::plots_dyn(apr_lines)
     ));
 
-    let plotting_area_size = [1500.0, 800.0];
+    let plotting_area_size = [settings.width, settings.height];
     let opt = poloto::render::render_opt_builder()
         .with_tick_lines([false, false])
         .with_dim(plotting_area_size)
@@ -150,9 +397,16 @@ fn main() -> Result<(), ScError> {
     let plotter = poloto::plot_with(
         data_for_plot,
         opt,
-        poloto::plot_fmt("Rewards", "", "DOT", xtick_fmt, ytick_fmt),
+        poloto::plot_fmt("Rewards", "", &chain.token_symbol, xtick_fmt, ytick_fmt),
     );
-    print!("{}", poloto::disp(|w| plotter.simple_theme_dark(w)));
+    let svg = match settings.theme {
+        Theme::Dark => poloto::disp(|w| plotter.simple_theme_dark(w)).to_string(),
+        Theme::Light => poloto::disp(|w| plotter.simple_theme(w)).to_string(),
+    };
+    match matches.value_of("output") {
+        Some(file) => fs::write(file, svg)?,
+        None => print!("{svg}"),
+    }
 
     Ok(())
 }
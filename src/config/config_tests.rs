@@ -0,0 +1,124 @@
+use super::*;
+
+const ADDR: &str = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+
+fn config(toml: &str) -> Config {
+    toml::from_str(toml).expect("Test config should parse")
+}
+
+/// The profile as the config file alone makes it, whatever the environment or .env say
+fn profile_of(toml: &str, name: Option<&str>) -> Result<Profile, ScError> {
+    Ok(config(toml).profile(name)?.with_env(|_| None))
+}
+
+#[test]
+fn profiles_are_picked_by_name_or_default() -> Result<(), Box<dyn std::error::Error>> {
+    let toml = r#"
+        default_profile = "polkadot"
+
+        [profiles.polkadot]
+        rpc_endpoint = "wss://polkadot.example"
+
+        [profiles.kusama]
+        rpc_endpoint = "wss://kusama.example"
+        subquery_page_size = 50
+    "#;
+
+    let profile = profile_of(toml, None)?;
+    assert_eq!(profile.name(), "polkadot");
+    assert_eq!(profile.rpc_endpoint()?, "wss://polkadot.example");
+    assert_eq!(profile.subquery_page_size()?, DEFAULT_PAGE_SIZE);
+    assert_eq!(
+        profile.polkadot_properties_file(),
+        "polkadot_properties.json"
+    );
+
+    let profile = profile_of(toml, Some("kusama"))?;
+    assert_eq!(profile.rpc_endpoint()?, "wss://kusama.example");
+    assert_eq!(profile.subquery_page_size()?, 50);
+
+    // Env variables override the profile's settings, unless empty
+    let with_env = profile.with_env(|var| match var {
+        "RPC_ENDPOINT" => Some("wss://env.example".into()),
+        _ => Some(String::new()),
+    });
+    assert_eq!(with_env.rpc_endpoint()?, "wss://env.example");
+    assert_eq!(with_env.subquery_page_size()?, 50);
+
+    assert!(matches!(
+        profile_of(toml, Some("westend")),
+        Err(ScError::UnknownProfile(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn several_profiles_need_a_default() {
+    let toml = r#"
+        [profiles.polkadot]
+        [profiles.kusama]
+    "#;
+    assert!(matches!(
+        profile_of(toml, None),
+        Err(ScError::InvalidConfig(_))
+    ));
+    assert!(profile_of("[profiles.polkadot]", None).is_ok());
+}
+
+#[test]
+fn unknown_settings_are_rejected() {
+    assert!(toml::from_str::<Config>("[profiles.polkadot]\nrpc_endpiont = \"x\"").is_err());
+}
+
+#[test]
+fn missing_settings_name_their_env_variable() {
+    let mut profile = profile_of("[profiles.polkadot]", None).unwrap();
+    assert!(matches!(profile.rpc_endpoint(), Err(ScError::NoEnvFile)));
+    profile.configured = true;
+    assert!(matches!(
+        profile.rpc_endpoint(),
+        Err(ScError::MissingEnvVariable(var)) if var == "RPC_ENDPOINT"
+    ));
+}
+
+#[test]
+fn profile_accounts_and_plot_settings() -> Result<(), Box<dyn std::error::Error>> {
+    let profile = profile_of(
+        &format!(
+            r#"
+        [profiles.polkadot]
+        known_rewards_file = "known_rewards.csv"
+
+        [profiles.polkadot.accounts]
+        stash = "{ADDR}"
+
+        [profiles.polkadot.plot]
+        sma_window_days = 7
+        apr_lines = 5
+        theme = "light"
+        "#
+        ),
+        None,
+    )?;
+
+    let accounts = profile.accounts()?;
+    assert_eq!(accounts, vec![Account::new("stash", ADDR)]);
+    assert_eq!(
+        profile.plot,
+        PlotSettings {
+            sma_window_days: 7,
+            apr_lines: 5,
+            theme: Theme::Light,
+            ..PlotSettings::default()
+        }
+    );
+
+    let profile = profile_of("[profiles.polkadot.accounts]\nstash = \"nope\"", None)?;
+    assert!(matches!(profile.accounts(), Err(ScError::InvalidConfig(_))));
+    let profile = profile_of(
+        &format!("[profiles.polkadot.accounts]\n\"../x\" = \"{ADDR}\""),
+        None,
+    )?;
+    assert!(matches!(profile.accounts(), Err(ScError::InvalidConfig(_))));
+    Ok(())
+}
@@ -0,0 +1,340 @@
+#[cfg(test)]
+mod config_tests;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::{parse_accounts, valid_account_name, valid_ss58_addr, DEFAULT_PAGE_SIZE};
+use crate::{Account, CsvStore, ScError, SqliteStore, Store};
+
+pub const CONFIG_FILE: &str = "stake-checker.toml";
+
+/// The contents of a config file, like
+/// ```toml
+/// default_profile = "polkadot"
+///
+/// [profiles.polkadot]
+/// rpc_endpoint = "wss://polkadot-rpc.dwellir.com"
+/// known_rewards_file = "known_rewards.csv"
+///
+/// [profiles.polkadot.accounts]
+/// stash = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD"
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// The settings of one chain and its accounts.
+/// Each setting can be overridden by the env variable of the same name in upper case,
+/// like RPC_ENDPOINT for rpc_endpoint.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    rpc_endpoint: Option<String>,
+    subquery_endpoint_rewards: Option<String>,
+    subquery_endpoint_stake_changes: Option<String>,
+    subquery_endpoint_pool_rewards: Option<String>,
+    subquery_page_size: Option<usize>,
+    polkadot_properties_file: Option<String>,
+    store: Option<String>,
+    known_rewards_file: Option<String>,
+    known_stake_changes_file: Option<String>,
+    known_pool_rewards_file: Option<String>,
+    known_balances_file: Option<String>,
+    sqlite_file: Option<String>,
+    /// Account names and their addresses
+    #[serde(default)]
+    accounts: BTreeMap<String, String>,
+    #[serde(default)]
+    pub plot: PlotSettings,
+    #[serde(skip)]
+    name: String,
+    /// Whether a .env file or a config file was found at all
+    #[serde(skip)]
+    configured: bool,
+    #[serde(skip)]
+    env: Env,
+}
+
+/// Looks up the env variables that override profile settings
+#[derive(Clone, Copy)]
+struct Env(fn(&str) -> Option<String>);
+
+/// The process environment, along with the .env file once it is loaded
+impl Default for Env {
+    fn default() -> Self {
+        Env(|var| dotenv::var(var).ok())
+    }
+}
+
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Env")
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotSettings {
+    /// Rewards expected per year, as a fraction of the stake
+    pub expected_apr: f64,
+    /// Lines of expected rewards are spread from expected_apr minus this to expected_apr plus this
+    pub apr_band: f64,
+    /// How many lines of expected rewards to draw. A single line is drawn at expected_apr.
+    pub apr_lines: usize,
+    /// Days to average rewards over
+    pub sma_window_days: u32,
+    pub width: f64,
+    pub height: f64,
+    pub theme: Theme,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        PlotSettings {
+            // Historical rewards rate on Dec 28, 2022
+            // https://staking.polkadot.network/#/overview
+            expected_apr: 0.1566,
+            apr_band: 0.05,
+            apr_lines: 3,
+            sma_window_days: 14,
+            width: 1500.0,
+            height: 800.0,
+            theme: Theme::Dark,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Config {
+    pub fn load(file: impl AsRef<Path>) -> Result<Self, ScError> {
+        let file = file.as_ref();
+        let invalid = |err: String| ScError::InvalidConfig(format!("{}: {err}", file.display()));
+        let contents = fs::read_to_string(file).map_err(|err| invalid(err.to_string()))?;
+        toml::from_str(&contents).map_err(|err| invalid(err.to_string()))
+    }
+
+    /// The named profile, or else the default profile.
+    /// A config with a single profile needs no default_profile.
+    pub fn profile(mut self, name: Option<&str>) -> Result<Profile, ScError> {
+        let (name, mut profile) = match name.map(String::from).or(self.default_profile) {
+            Some(name) => match self.profiles.remove(&name) {
+                Some(profile) => (name, profile),
+                None => return Err(ScError::UnknownProfile(name)),
+            },
+            None if self.profiles.len() > 1 => {
+                return Err(ScError::InvalidConfig(
+                    "several profiles but no default_profile, pick one with --profile".into(),
+                ))
+            }
+            None => self
+                .profiles
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| ("default".into(), Profile::default())),
+        };
+        profile.name = name;
+        Ok(profile)
+    }
+}
+
+/// Finds the config file: the one given, or else STAKE_CHECKER_CONFIG,
+/// or stake-checker.toml in the working directory,
+/// or stake-checker/config.toml in the XDG config directory.
+pub fn find_config_file(given: Option<&str>) -> Option<PathBuf> {
+    if let Some(file) = given {
+        return Some(file.into());
+    }
+    if let Ok(file) = dotenv::var("STAKE_CHECKER_CONFIG") {
+        return Some(file.into());
+    }
+    let in_working_dir = PathBuf::from(CONFIG_FILE);
+    if in_working_dir.is_file() {
+        return Some(in_working_dir);
+    }
+    let config_home = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").ok()?).join(".config"),
+    };
+    Some(config_home.join("stake-checker").join("config.toml")).filter(|file| file.is_file())
+}
+
+/// Loads the .env file if any, and the profile to run with.
+/// The profile is picked by name, or else by STAKE_CHECKER_PROFILE.
+pub fn load_profile(config_file: Option<&str>, profile: Option<&str>) -> Result<Profile, ScError> {
+    let env_file_found = dotenv::dotenv().is_ok();
+    let env_profile = dotenv::var("STAKE_CHECKER_PROFILE").ok();
+    let profile = profile.or(env_profile.as_deref());
+    let config = match find_config_file(config_file) {
+        Some(file) => Config::load(file)?,
+        None => match profile {
+            Some(name) => return Err(ScError::UnknownProfile(name.into())),
+            None => Config::default(),
+        },
+    };
+    let configured = env_file_found || !config.profiles.is_empty();
+    let mut profile = config.profile(profile)?;
+    profile.configured = configured;
+    Ok(profile)
+}
+
+impl Profile {
+    /// Looks up env variables with `env` instead of in the process environment and .env
+    pub fn with_env(mut self, env: fn(&str) -> Option<String>) -> Self {
+        self.env = Env(env);
+        self
+    }
+
+    /// Env variables win over profile settings, except empty ones.
+    fn setting(&self, var: &str, value: &Option<String>) -> Option<String> {
+        match (self.env.0)(var) {
+            Some(s) if !s.is_empty() => Some(s),
+            _ => value.clone().filter(|s| !s.is_empty()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn missing(&self, var: &str) -> ScError {
+        match self.configured {
+            true => ScError::MissingEnvVariable(var.into()),
+            false => ScError::NoEnvFile,
+        }
+    }
+
+    fn required(&self, var: &str, value: &Option<String>) -> Result<String, ScError> {
+        self.setting(var, value).ok_or_else(|| self.missing(var))
+    }
+
+    pub fn rpc_endpoint(&self) -> Result<String, ScError> {
+        self.required("RPC_ENDPOINT", &self.rpc_endpoint)
+    }
+
+    pub fn subquery_endpoint_rewards(&self) -> Result<String, ScError> {
+        self.required("SUBQUERY_ENDPOINT_REWARDS", &self.subquery_endpoint_rewards)
+    }
+
+    pub fn subquery_endpoint_stake_changes(&self) -> Result<String, ScError> {
+        self.required(
+            "SUBQUERY_ENDPOINT_STAKE_CHANGES",
+            &self.subquery_endpoint_stake_changes,
+        )
+    }
+
+    /// Pool rewards are only synced if an endpoint is set for them
+    pub fn subquery_endpoint_pool_rewards(&self) -> Option<String> {
+        self.setting(
+            "SUBQUERY_ENDPOINT_POOL_REWARDS",
+            &self.subquery_endpoint_pool_rewards,
+        )
+    }
+
+    pub fn subquery_page_size(&self) -> Result<usize, ScError> {
+        let page_size = match (self.env.0)("SUBQUERY_PAGE_SIZE").filter(|s| !s.is_empty()) {
+            Some(s) => s
+                .parse()
+                .map_err(|_| ScError::InvalidEnvVariable("SUBQUERY_PAGE_SIZE".into(), s))?,
+            None => self.subquery_page_size.unwrap_or(DEFAULT_PAGE_SIZE),
+        };
+        match page_size {
+            0 => Err(ScError::InvalidEnvVariable(
+                "SUBQUERY_PAGE_SIZE".into(),
+                "0".into(),
+            )),
+            page_size => Ok(page_size),
+        }
+    }
+
+    /// Profiles keep their chain's properties apart by default
+    pub fn polkadot_properties_file(&self) -> String {
+        self.setting("POLKADOT_PROPERTIES_FILE", &self.polkadot_properties_file)
+            .unwrap_or_else(|| format!("{}_properties.json", self.name))
+    }
+
+    /// The accounts listed in POLKADOT_ACCOUNTS, or else the single account in POLKADOT_ADDR,
+    /// or else the accounts of the profile.
+    pub fn accounts(&self) -> Result<Vec<Account>, ScError> {
+        if let Some(list) = self.setting("POLKADOT_ACCOUNTS", &None) {
+            return parse_accounts(&list);
+        }
+        if let Some(addr) = self.setting("POLKADOT_ADDR", &None) {
+            return Ok(vec![Account {
+                name: "default".into(),
+                addr: valid_ss58_addr(&addr)?,
+                own_files: false,
+            }]);
+        }
+        if self.accounts.is_empty() {
+            return Err(self.missing("POLKADOT_ADDR"));
+        }
+        self.accounts
+            .iter()
+            .map(|(name, addr)| {
+                if !valid_account_name(name) {
+                    return Err(ScError::InvalidConfig(format!(
+                        "accounts.{name}: names may only have letters, digits, _ and -"
+                    )));
+                }
+                let addr = valid_ss58_addr(addr).map_err(|_| {
+                    ScError::InvalidConfig(format!("accounts.{name} is no address: {addr}"))
+                })?;
+                Ok(Account::new(name, addr))
+            })
+            .collect()
+    }
+
+    /// Opens an account's store, as selected by the store setting.
+    /// That is the csv files of known data, unless store is sqlite.
+    pub fn store_for_account(&self, account: &Account) -> Result<Box<dyn Store>, ScError> {
+        let file = |var: &str, value: &Option<String>| {
+            account.file_name(&self.setting(var, value).unwrap_or_default())
+        };
+        match self.setting("STORE", &self.store).as_deref() {
+            Some("sqlite") => Ok(Box::new(SqliteStore::open(
+                account.file_name(
+                    &self
+                        .setting("SQLITE_FILE", &self.sqlite_file)
+                        .unwrap_or_else(|| "stake-checker.sqlite".into()),
+                ),
+            )?)),
+            Some("csv") | None => {
+                let mut store = CsvStore::new(
+                    file("KNOWN_REWARDS_FILE", &self.known_rewards_file),
+                    file("KNOWN_STAKE_CHANGES_FILE", &self.known_stake_changes_file),
+                )
+                .with_pool_rewards_file(
+                    account.file_name(
+                        &self
+                            .setting("KNOWN_POOL_REWARDS_FILE", &self.known_pool_rewards_file)
+                            .unwrap_or_else(|| "known_pool_rewards.csv".into()),
+                    ),
+                );
+                if self
+                    .setting("KNOWN_BALANCES_FILE", &self.known_balances_file)
+                    .is_some()
+                {
+                    store = store
+                        .with_balances_file(file("KNOWN_BALANCES_FILE", &self.known_balances_file));
+                }
+                Ok(Box::new(store))
+            }
+            Some(other) => Err(ScError::InvalidEnvVariable("STORE".into(), other.into())),
+        }
+    }
+}
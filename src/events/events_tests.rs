@@ -0,0 +1,271 @@
+use super::*;
+use crate::tests::{metadata_bytes, mock_rpc_result, pallet, plain_entry};
+use crate::SqliteStore;
+
+use chrono::NaiveDate;
+use parity_scale_codec::Encode;
+use scale_info::TypeInfo;
+use serde_json::json;
+
+const ADDR: &str = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+const VALIDATOR: &str = "1zugcag7cJVBtVRnFxv5Qftn7xKAnR6YJ9x4x3XLgGgmNnS";
+
+#[derive(Encode, TypeInfo)]
+#[allow(dead_code)]
+enum Phase {
+    ApplyExtrinsic(u32),
+    Finalization,
+    Initialization,
+}
+
+#[derive(Encode, TypeInfo)]
+#[allow(dead_code)]
+enum RewardDestination {
+    Staked,
+    Account(AccountId32),
+}
+
+#[derive(Encode, TypeInfo)]
+enum StakingEvent {
+    PayoutStarted {
+        era_index: u32,
+        validator_stash: AccountId32,
+    },
+    Rewarded {
+        stash: AccountId32,
+        dest: RewardDestination,
+        amount: u128,
+    },
+}
+
+#[derive(Encode, TypeInfo)]
+enum NominationPoolsEvent {
+    PaidOut {
+        member: AccountId32,
+        pool_id: u32,
+        payout: u128,
+    },
+}
+
+#[derive(Encode, TypeInfo)]
+enum RuntimeEvent {
+    Staking(StakingEvent),
+    NominationPools(NominationPoolsEvent),
+}
+
+#[derive(Encode, TypeInfo)]
+struct EventRecord {
+    phase: Phase,
+    event: RuntimeEvent,
+    topics: Vec<[u8; 32]>,
+}
+
+/// The metadata as the node gives it, with System.Events and Timestamp.Now
+fn test_metadata_bytes() -> Vec<u8> {
+    metadata_bytes(vec![
+        pallet("System", 0, vec![plain_entry::<Vec<EventRecord>>("Events")]),
+        pallet("Timestamp", 3, vec![plain_entry::<u64>("Now")]),
+    ])
+}
+
+fn test_metadata() -> Metadata {
+    Metadata::decode(&test_metadata_bytes()).unwrap()
+}
+
+fn record(extrinsic: u32, event: RuntimeEvent) -> EventRecord {
+    EventRecord {
+        phase: Phase::ApplyExtrinsic(extrinsic),
+        event,
+        topics: vec![],
+    }
+}
+
+#[tokio::test]
+async fn block_rewards_are_read_from_system_events() -> Result<(), Box<dyn std::error::Error>> {
+    let account_id = AccountId32::from_string(ADDR)?;
+    let validator = AccountId32::from_string(VALIDATOR)?;
+    let events = vec![
+        record(
+            1,
+            RuntimeEvent::Staking(StakingEvent::PayoutStarted {
+                era_index: 1000,
+                validator_stash: validator.clone(),
+            }),
+        ),
+        record(
+            1,
+            RuntimeEvent::Staking(StakingEvent::Rewarded {
+                stash: validator.clone(),
+                dest: RewardDestination::Staked,
+                amount: 1,
+            }),
+        ),
+        record(
+            1,
+            RuntimeEvent::Staking(StakingEvent::Rewarded {
+                stash: account_id.clone(),
+                dest: RewardDestination::Account(validator),
+                amount: u128::MAX,
+            }),
+        ),
+        record(
+            2,
+            RuntimeEvent::NominationPools(NominationPoolsEvent::PaidOut {
+                member: account_id,
+                pool_id: 12,
+                payout: 5_000_000_000,
+            }),
+        ),
+    ];
+    let block_hash = "0x91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3";
+    let date = NaiveDate::from_ymd(2023, 1, 1).and_hms_milli(12, 0, 6, 1);
+    let metadata = test_metadata();
+    let mocks = [
+        mock_rpc_result("chain_getBlockHash", json!([200]), json!(block_hash)),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([metadata.storage_key("System", "Events", &[])?, block_hash]),
+            json!(format!("0x{}", hex::encode(events.encode()))),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([metadata.storage_key("Timestamp", "Now", &[])?, block_hash]),
+            json!(format!(
+                "0x{}",
+                hex::encode((date.timestamp_millis() as u64).encode())
+            )),
+        ),
+    ];
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let rewards = get_block_rewards(&rpc, &metadata, ADDR, 200).await?;
+
+    for mock in mocks {
+        mock.assert();
+    }
+    assert_eq!(
+        rewards,
+        vec![
+            ChainReward {
+                block: 200,
+                era: Some(1000),
+                pool_id: None,
+                reward: Reward {
+                    date,
+                    balance: Balance::from(u128::MAX),
+                    id: Some("200-2".into()),
+                },
+            },
+            ChainReward {
+                block: 200,
+                era: None,
+                pool_id: Some(12),
+                reward: Reward {
+                    date,
+                    balance: Balance::from(5_000_000_000),
+                    id: Some("200-3".into()),
+                },
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn runtime_upgrades_refetch_metadata() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata_hex = format!("0x{}", hex::encode(test_metadata_bytes()));
+    let events_key = test_metadata().storage_key("System", "Events", &[])?;
+    let mut mocks = vec![];
+    // The runtime is upgraded in block 301, and block 302 keeps its metadata
+    for (number, spec_version) in [(300u32, 9430u32), (301, 1_000_000), (302, 1_000_000)] {
+        let hash = format!("0x{:064x}", number);
+        mocks.push(mock_rpc_result(
+            "chain_getBlockHash",
+            json!([number]),
+            json!(hash),
+        ));
+        mocks.push(mock_rpc_result(
+            "state_getRuntimeVersion",
+            json!([hash]),
+            json!({ "specName": "polkadot", "specVersion": spec_version }),
+        ));
+        if number != 302 {
+            mocks.push(mock_rpc_result(
+                "state_getMetadata",
+                json!([hash]),
+                json!(metadata_hex),
+            ));
+        }
+        mocks.push(mock_rpc_result(
+            "state_getStorage",
+            json!([events_key, hash]),
+            json!(null),
+        ));
+    }
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let rewards = get_chain_rewards(&rpc, ADDR, 300, 302).await?;
+
+    for mock in mocks {
+        mock.assert();
+    }
+    assert_eq!(rewards, vec![]);
+    Ok(())
+}
+
+#[test]
+fn events_of_older_runtimes_have_unnamed_fields() -> Result<(), Box<dyn std::error::Error>> {
+    let account_id = AccountId32::from_string(ADDR)?;
+    let date = NaiveDate::from_ymd(2021, 1, 1).and_hms(0, 0, 0);
+    let events = json!([
+        { "phase": { "ApplyExtrinsic": 1 }, "event": { "Staking": { "PayoutStarted": [500, VALIDATOR] } } },
+        { "phase": { "ApplyExtrinsic": 1 }, "event": { "Staking": { "Rewarded": [ADDR, "1000"] } } },
+        // A reward in another extrinsic than the payout is not of the payout's era
+        { "phase": { "ApplyExtrinsic": 2 }, "event": { "Staking": { "Rewarded": [ADDR, "2000"] } } },
+        { "phase": "Finalization", "event": { "Staking": "StakingElection" } },
+    ]);
+
+    let rewards = rewards_in_events(&events, &account_id, 100, date);
+
+    assert_eq!(
+        rewards
+            .iter()
+            .map(|r| (r.era, r.reward.balance.planck, r.reward.id.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            (Some(500), 1000, Some("100-1")),
+            (None, 2000, Some("100-2"))
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn chain_rewards_are_added_to_the_store_once() -> Result<(), Box<dyn std::error::Error>> {
+    let file = testfile::generate_name();
+    let _tf = testfile::from_file(&file);
+    let mut store = SqliteStore::open(&file)?;
+    let chain_reward = |index: u32, pool_id| ChainReward {
+        block: 200,
+        era: None,
+        pool_id,
+        reward: Reward {
+            date: NaiveDate::from_ymd(2023, 1, 1).and_hms(12, 0, 0),
+            balance: Balance::from(1),
+            id: Some(format!("200-{index}")),
+        },
+    };
+    // As if an indexer had told of the first one already
+    store.add_rewards(&[chain_reward(1, None).reward])?;
+    let chain_rewards = [
+        chain_reward(1, None),
+        chain_reward(2, None),
+        chain_reward(3, Some(12)),
+    ];
+
+    assert_eq!(add_chain_rewards(&mut store, &chain_rewards)?, (1, 1));
+    assert_eq!(add_chain_rewards(&mut store, &chain_rewards)?, (0, 0));
+    assert_eq!(store.rewards()?.len(), 2);
+    assert_eq!(store.pool_rewards()?, vec![chain_rewards[2].reward.clone()]);
+    Ok(())
+}
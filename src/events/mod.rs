@@ -0,0 +1,196 @@
+#[cfg(test)]
+mod events_tests;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sp_core::crypto::{AccountId32, Ss58Codec};
+
+use crate::staking::account_id;
+use crate::{
+    block_hash, block_timestamp_millis, drop_known, fetch_metadata, runtime_spec_version,
+    state_get_storage, Balance, Metadata, Reward, RpcClient, ScError, Store,
+};
+
+/// A reward to an account, as told by an event of the block that paid it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainReward {
+    pub block: u32,
+    /// The era paid out. Pool payouts don't tell.
+    pub era: Option<u32>,
+    /// The nomination pool that paid the reward. None for staking rewards.
+    pub pool_id: Option<u32>,
+    #[serde(flatten)]
+    pub reward: Reward,
+}
+
+/// The pallet, name and fields of an event decoded as json,
+/// like `{"Staking": {"Rewarded": {"stash": ..., "amount": ...}}}`
+fn event_parts(event: &Value) -> Option<(&str, &str, &Value)> {
+    let (pallet, pallet_event) = event.as_object()?.iter().next()?;
+    let (name, fields) = pallet_event.as_object()?.iter().next()?;
+    Some((pallet, name, fields))
+}
+
+/// Older runtimes have events with unnamed fields, which are looked up by position instead
+fn field<'a>(fields: &'a Value, name: &str, position: usize) -> Option<&'a Value> {
+    match fields {
+        Value::Object(fields) => fields.get(name),
+        Value::Array(fields) => fields.get(position),
+        _ => None,
+    }
+}
+
+fn is_account(value: Option<&Value>, account_id: &AccountId32) -> bool {
+    value
+        .and_then(Value::as_str)
+        .and_then(|addr| AccountId32::from_ss58check_with_version(addr).ok())
+        .is_some_and(|(id, _)| id == *account_id)
+}
+
+fn balance(value: Option<&Value>) -> Option<Balance> {
+    value.and_then(|value| Balance::deserialize(value).ok())
+}
+
+/// Finds the rewards to an account among a block's System.Events, as decoded by the runtime metadata.
+/// Rewards are given ids like those of the indexers, the block number and the event's index,
+/// so rewards found both ways are only kept once.
+pub fn rewards_in_events(
+    events: &Value,
+    account_id: &AccountId32,
+    block: u32,
+    date: NaiveDateTime,
+) -> Vec<ChainReward> {
+    let chain_reward = |index: usize, balance: Balance, era, pool_id| ChainReward {
+        block,
+        era,
+        pool_id,
+        reward: Reward {
+            date,
+            balance,
+            id: Some(format!("{block}-{index}")),
+        },
+    };
+    let mut rewards = vec![];
+    // An era's payout starts with PayoutStarted, followed by a Rewarded per staker
+    // in the same extrinsic
+    let mut payout: Option<(&Value, u32)> = None;
+    for (index, record) in events.as_array().into_iter().flatten().enumerate() {
+        let phase = &record["phase"];
+        let Some((pallet, name, fields)) = event_parts(&record["event"]) else {
+            continue;
+        };
+        match (pallet, name) {
+            ("Staking", "PayoutStarted") => {
+                payout = field(fields, "era_index", 0)
+                    .and_then(Value::as_u64)
+                    .map(|era| (phase, era as u32));
+            }
+            ("Staking", "Rewarded") if is_account(field(fields, "stash", 0), account_id) => {
+                let era = payout
+                    .filter(|(payout_phase, _)| *payout_phase == phase)
+                    .map(|(_, era)| era);
+                if let Some(amount) = balance(field(fields, "amount", 1)) {
+                    rewards.push(chain_reward(index, amount, era, None));
+                }
+            }
+            ("NominationPools", "PaidOut")
+                if is_account(field(fields, "member", 0), account_id) =>
+            {
+                let pool_id = field(fields, "pool_id", 1)
+                    .and_then(Value::as_u64)
+                    .map(|id| id as u32);
+                if let Some(payout) = balance(field(fields, "payout", 2)) {
+                    rewards.push(chain_reward(index, payout, None, pool_id));
+                }
+            }
+            _ => (),
+        }
+    }
+    rewards
+}
+
+/// Rewards to the account paid in a block, read from the block's System.Events.
+/// The metadata must be that of the runtime the block was made with.
+pub async fn get_block_rewards(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    polkadot_addr: &str,
+    number: u32,
+) -> Result<Vec<ChainReward>, ScError> {
+    let account_id = account_id(polkadot_addr)?;
+    let hash = block_hash(rpc, number).await?;
+    block_rewards_at(rpc, metadata, &account_id, number, &hash).await
+}
+
+async fn block_rewards_at(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    account_id: &AccountId32,
+    number: u32,
+    hash: &str,
+) -> Result<Vec<ChainReward>, ScError> {
+    let events_bytes =
+        match state_get_storage(rpc, metadata, "System", "Events", &[], Some(hash)).await {
+            Ok(bytes) => bytes,
+            Err(ScError::NoDataFound) => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+    let events = metadata.decode_storage_value("System", "Events", &events_bytes)?;
+    let millis = block_timestamp_millis(rpc, metadata, hash).await?;
+    let date = NaiveDateTime::from_timestamp_opt(
+        (millis / 1000) as i64,
+        (millis % 1000) as u32 * 1_000_000,
+    )
+    .ok_or(ScError::NoDataFound)?;
+    Ok(rewards_in_events(&events, account_id, number, date))
+}
+
+/// Rewards to the account paid in blocks `from` to `to`, both included, read from the chain
+/// without an indexer. Every block is read, so this is slow for long ranges,
+/// and the node must keep the state of old blocks, as archive nodes do.
+pub async fn get_chain_rewards(
+    rpc: &RpcClient,
+    polkadot_addr: &str,
+    from: u32,
+    to: u32,
+) -> Result<Vec<ChainReward>, ScError> {
+    let account_id = account_id(polkadot_addr)?;
+    let mut runtime: Option<(u32, Metadata)> = None;
+    let mut rewards = vec![];
+    for number in from..=to {
+        let hash = block_hash(rpc, number).await?;
+        let spec_version = runtime_spec_version(rpc, &hash).await?;
+        // Events change with runtime upgrades, and so does the metadata telling their types
+        let metadata = match runtime.take() {
+            Some((version, metadata)) if version == spec_version => metadata,
+            _ => fetch_metadata(rpc, Some(&hash)).await?,
+        };
+        rewards.extend(block_rewards_at(rpc, &metadata, &account_id, number, &hash).await?);
+        runtime = Some((spec_version, metadata));
+    }
+    Ok(rewards)
+}
+
+/// Adds staking and pool rewards read from the chain to the store, skipping those it knows.
+/// Returns how many staking rewards and how many pool rewards were added.
+pub fn add_chain_rewards(
+    store: &mut dyn Store,
+    chain_rewards: &[ChainReward],
+) -> Result<(usize, usize), ScError> {
+    let (pool_rewards, rewards): (Vec<_>, Vec<_>) = chain_rewards
+        .iter()
+        .partition(|chain_reward| chain_reward.pool_id.is_some());
+    let rewards = drop_known(
+        &store.rewards()?,
+        rewards.into_iter().map(|r| r.reward.clone()).collect(),
+    );
+    let pool_rewards = drop_known(
+        &store.pool_rewards()?,
+        pool_rewards.into_iter().map(|r| r.reward.clone()).collect(),
+    );
+    Ok((
+        store.add_rewards(&rewards)?,
+        store.add_pool_rewards(&pool_rewards)?,
+    ))
+}
@@ -1,8 +1,19 @@
 #[cfg(test)]
 mod tests;
 
+mod balance;
+mod config;
+mod events;
+mod metadata;
+mod output;
+mod pools;
+mod runtime_api;
+mod staking;
+mod store;
+mod transport;
 mod util;
 
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs;
 use std::path::Path;
@@ -12,24 +23,76 @@ use chrono::NaiveDateTime;
 use frame_metadata::RuntimeMetadataPrefixed;
 use parity_scale_codec::Decode;
 use parity_scale_codec::Error as ParityScaleError;
-use serde::Deserialize;
-use sp_core::{crypto::AccountId32, crypto::Ss58Codec, hashing};
+use serde::{Deserialize, Serialize};
+use sp_core::crypto::{AccountId32, Ss58AddressFormat, Ss58Codec};
+
+pub use balance::{Balance, Rounding, WithSymbol};
+pub use config::{find_config_file, load_profile, Config, PlotSettings, Profile, Theme};
+pub use events::{
+    add_chain_rewards, get_block_rewards, get_chain_rewards, rewards_in_events, ChainReward,
+};
+pub use metadata::{Metadata, PalletSummary};
+pub use output::{
+    print_csv, print_json, AccountBalances, AccountInfo, Amount, NominationsStatus, Nominee,
+    OfAccount, OutputFormat, PoolMembership, StakingStatus, Unbonding,
+};
+pub use pools::{
+    get_bonded_pool, get_pending_pool_rewards, get_pool_member, BondedPool, PoolMember,
+};
+pub use runtime_api::{
+    get_nominations_quota, query_fee_info, runtime_call, DispatchClass, RuntimeDispatchInfo, Weight,
+};
+pub use staking::{
+    era_length_millis, get_active_era, get_controller, get_era_apr, get_identity_name,
+    get_nomination_targets, get_nominations, get_payee, get_recent_era_aprs, get_staking_ledger,
+    get_staking_state, get_validator_prefs, is_active_validator, mean_apr, realised_apr, ActiveEra,
+    EraApr, NominationTarget, Nominations, RewardDestination, StakingLedger, StakingState,
+    UnlockChunk, ValidatorPrefs,
+};
+pub use store::{BalanceSnapshot, CsvStore, SqliteStore, Store, SyncCursor};
+pub use transport::{RpcClient, Subscription};
 
 pub type TokenDecimals = usize;
 
-pub type PolkadotAccountInfo = pallet_system::AccountInfo<u32, pallet_balances::AccountData<u128>>;
-pub type Stringifier = fn(&[u8], TokenDecimals) -> Result<String, ScError>;
+pub type PolkadotAccountInfo =
+    pallet_system::AccountInfo<u32, pallet_balances::AccountData<Balance>>;
+pub type Stringifier = fn(&[u8], &ChainProfile) -> Result<String, ScError>;
+pub type Jsonifier = fn(&[u8], &ChainProfile) -> Result<serde_json::Value, ScError>;
 
 pub enum ScError {
     NoEnvFile,
     MissingEnvVariable(String),
+    MissingSetting(String, String),
+    InvalidEnvVariable(String, String),
     InvalidPolkadotAddr(String),
+    NotBonded(String),
+    NotNominating(String),
+    NotPoolMember(String),
+    WrongChainAddr(String, u16),
+    UnknownAccount(String),
+    UnknownProfile(String),
+    InvalidConfig(String),
+    OneAccountOnly(String),
+    UnknownOutputFormat(String),
+    UnsupportedOutput(OutputFormat, String),
     NoDataFound,
+    UnknownBlock(String),
+    InvalidDate(String),
+    InvalidBalance(String),
+    UnsupportedMetadata(u32),
+    UnknownStorage(String),
+    UnknownConstant(String),
+    InvalidStorageKey(String),
+    InvalidStorageValue(String),
     IO(std::io::Error),
     Reqwest(reqwest::Error),
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    ConnectionClosed,
+    SubscriptionNeedsWebSocket,
     Codec(ParityScaleError),
     Json(serde_json::Error),
     Csv(csv::Error),
+    Sqlite(rusqlite::Error),
     Anyhow(anyhow::Error),
 }
 
@@ -39,20 +102,97 @@ impl fmt::Display for ScError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ScError::MissingEnvVariable(var) => {
-                write!(f, "No {var} set in .env")
+                write!(
+                    f,
+                    "No {var} set in .env, nor {} in the config profile",
+                    var.to_lowercase()
+                )
+            }
+            ScError::MissingSetting(var, command) => {
+                write!(
+                    f,
+                    "{command} needs {var} set in .env, or {} in the config profile",
+                    var.to_lowercase()
+                )
+            }
+            ScError::InvalidEnvVariable(var, value) => {
+                write!(f, "Invalid {var} found in .env or config: {value}")
             }
             ScError::InvalidPolkadotAddr(addr) => {
                 write!(f, "Invalid POLKADOT_ADDR found in .env: {addr}")
             }
+            ScError::NotBonded(addr) => write!(f, "{addr} is not bonded for staking"),
+            ScError::NotNominating(addr) => write!(f, "{addr} does not nominate any validators"),
+            ScError::NotPoolMember(addr) => write!(f, "{addr} is not in a nomination pool"),
+            ScError::WrongChainAddr(addr, format) => {
+                write!(
+                    f,
+                    "{addr} is not an address of this chain, which uses SS58 format {format}"
+                )
+            }
+            ScError::UnknownAccount(name) => {
+                write!(f, "No account named {name} in the config or in .env")
+            }
+            ScError::UnknownProfile(name) => {
+                write!(f, "No profile named {name} in the config file")
+            }
+            ScError::InvalidConfig(err) => write!(f, "Invalid config {err}"),
+            ScError::OneAccountOnly(what) => {
+                write!(
+                    f,
+                    "{what} works on one account at a time, pick one with --account"
+                )
+            }
+            ScError::UnknownOutputFormat(format) => {
+                write!(
+                    f,
+                    "Unknown output format {format}, expected table, json or csv"
+                )
+            }
+            ScError::UnsupportedOutput(format, command) => {
+                write!(f, "{command} has no {format} output")
+            }
             ScError::NoDataFound => {
                 write!(f, "Did not find any data. Polkadot address unused?")
             }
-            ScError::NoEnvFile => write!(f, "Can't find .env file."),
+            ScError::UnknownBlock(block) => write!(f, "Can't find block {block}"),
+            ScError::InvalidDate(date) => {
+                write!(
+                    f,
+                    "Invalid date {date}, expected a format like 2023-01-01T00:00:00 or 2023-01-01"
+                )
+            }
+            ScError::InvalidBalance(balance) => write!(f, "Invalid balance {balance}"),
+            ScError::UnsupportedMetadata(version) => {
+                write!(f, "Unsupported runtime metadata version V{version}")
+            }
+            ScError::UnknownStorage(storage) => {
+                write!(f, "Can't find storage {storage} in runtime metadata")
+            }
+            ScError::UnknownConstant(constant) => {
+                write!(f, "Can't find constant {constant} in runtime metadata")
+            }
+            ScError::InvalidStorageKey(key) => write!(f, "Invalid storage key {key}"),
+            ScError::InvalidStorageValue(value) => write!(f, "Invalid storage value {value}"),
+            ScError::NoEnvFile => write!(
+                f,
+                "Can't find .env file, nor a {} config file.",
+                config::CONFIG_FILE
+            ),
             ScError::IO(err) => write!(f, "Error while flushing the file {}", err),
             ScError::Reqwest(err) => write!(f, "Error while fetching data {}", err),
+            ScError::WebSocket(err) => write!(f, "WebSocket error {}", err),
+            ScError::ConnectionClosed => write!(f, "Connection to rpc node was closed"),
+            ScError::SubscriptionNeedsWebSocket => {
+                write!(
+                    f,
+                    "Subscriptions need a ws:// or wss:// RPC_ENDPOINT in .env"
+                )
+            }
             ScError::Codec(err) => write!(f, "Codec error {}", err),
             ScError::Json(err) => write!(f, "Json error {}", err),
             ScError::Csv(err) => write!(f, "Comma separated value error {}", err),
+            ScError::Sqlite(err) => write!(f, "Sqlite error {}", err),
             ScError::Anyhow(err) => write!(f, "Anyhow err: {err}"),
         }
     }
@@ -76,6 +216,12 @@ impl From<csv::Error> for ScError {
     }
 }
 
+impl From<rusqlite::Error> for ScError {
+    fn from(err: rusqlite::Error) -> ScError {
+        ScError::Sqlite(err)
+    }
+}
+
 impl From<serde_json::Error> for ScError {
     fn from(err: serde_json::Error) -> ScError {
         ScError::Json(err)
@@ -94,66 +240,254 @@ impl From<reqwest::Error> for ScError {
     }
 }
 
+impl From<tokio_tungstenite::tungstenite::Error> for ScError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> ScError {
+        ScError::WebSocket(Box::new(err))
+    }
+}
+
 impl From<std::io::Error> for ScError {
     fn from(err: std::io::Error) -> ScError {
         ScError::IO(err)
     }
 }
 
-pub fn known_stake_changes_file_from_env() -> String {
-    match dotenv::var("KNOWN_STAKE_CHANGES_FILE") {
-        Ok(s) => s,
-        Err(_) => "".into(),
+/// A staking account, as listed in the config or in .env.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    pub addr: String,
+    /// Accounts listed in POLKADOT_ACCOUNTS keep their data in files of their own
+    own_files: bool,
+}
+
+impl Account {
+    pub fn new(name: impl Into<String>, addr: impl Into<String>) -> Self {
+        Account {
+            name: name.into(),
+            addr: addr.into(),
+            own_files: true,
+        }
+    }
+
+    /// This account's version of a configured file name.
+    /// For an account named stash, known_rewards.csv becomes known_rewards_stash.csv.
+    pub fn file_name(&self, file: &str) -> String {
+        if !self.own_files || file.is_empty() {
+            return file.into();
+        }
+        let path = Path::new(file);
+        match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => path
+                .with_file_name(format!(
+                    "{}_{}.{}",
+                    stem.to_string_lossy(),
+                    self.name,
+                    extension.to_string_lossy()
+                ))
+                .to_string_lossy()
+                .into_owned(),
+            _ => format!("{file}_{}", self.name),
+        }
     }
 }
 
-pub fn known_rewards_file_from_env() -> String {
-    match dotenv::var("KNOWN_REWARDS_FILE") {
-        Ok(s) => s,
-        Err(_) => "".into(),
+/// Checks that an address is well formed, whichever chain it is for.
+/// Whether it is for the chain at hand is up to ChainProfile::check_accounts.
+fn valid_ss58_addr(addr: &str) -> Result<String, ScError> {
+    let (account_id, format) = AccountId32::from_ss58check_with_version(addr)
+        .map_err(|_| ScError::InvalidPolkadotAddr(addr.into()))?;
+    let back_to_string = account_id.to_ss58check_with_version(format);
+    if addr != back_to_string {
+        return Err(ScError::InvalidPolkadotAddr(addr.into()));
     }
+    Ok(back_to_string)
 }
 
-pub fn polkadot_properties_file_from_env() -> String {
-    match dotenv::var("POLKADOT_PROPERTIES_FILE") {
-        Ok(s) => s,
-        Err(_) => "".into(),
+/// Account names become part of file names, so they are kept to letters, digits, _ and -
+fn valid_account_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn parse_accounts(list: &str) -> Result<Vec<Account>, ScError> {
+    let mut accounts: Vec<Account> = vec![];
+    for entry in list.split(',').map(str::trim) {
+        let invalid = || ScError::InvalidEnvVariable("POLKADOT_ACCOUNTS".into(), entry.into());
+        let (name, addr) = entry.split_once('=').ok_or_else(invalid)?;
+        let (name, addr) = (name.trim(), addr.trim());
+        if !valid_account_name(name) || accounts.iter().any(|account| account.name == name) {
+            return Err(invalid());
+        }
+        let addr = valid_ss58_addr(addr).map_err(|_| invalid())?;
+        accounts.push(Account::new(name, addr));
+    }
+    Ok(accounts)
+}
+
+/// The accounts a command runs for: the one named, or else all of them.
+pub fn select_accounts(
+    accounts: Vec<Account>,
+    name: Option<&str>,
+) -> Result<Vec<Account>, ScError> {
+    match name {
+        Some(name) => match accounts.into_iter().find(|account| account.name == name) {
+            Some(account) => Ok(vec![account]),
+            None => Err(ScError::UnknownAccount(name.into())),
+        },
+        None => Ok(accounts),
+    }
+}
+
+/// What sets one chain's addresses and amounts apart from another's,
+/// as told by the chain's system properties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainProfile {
+    pub ss58_format: u16,
+    pub token_symbol: String,
+    pub token_decimals: TokenDecimals,
+}
+
+impl ChainProfile {
+    /// Chains with several tokens list several symbols and decimals.
+    /// The first ones are for the native token.
+    pub fn from_properties(properties: &serde_json::Value) -> Self {
+        let native = |key: &str| match &properties[key] {
+            serde_json::Value::Array(values) => values.first().cloned().unwrap_or_default(),
+            value => value.clone(),
+        };
+        ChainProfile {
+            ss58_format: properties["ss58Format"]
+                .as_u64()
+                .and_then(|format| u16::try_from(format).ok())
+                .unwrap_or(42),
+            token_symbol: native("tokenSymbol").as_str().unwrap_or("UNIT").into(),
+            token_decimals: native("tokenDecimals").as_u64().unwrap_or(0) as TokenDecimals,
+        }
+    }
+
+    pub fn from_file(file: impl AsRef<Path>) -> Result<Self, ScError> {
+        let prop_str =
+            fs::read_to_string(file).with_context(|| "could not open polkadot properties file")?;
+        let properties: serde_json::Value = serde_json::from_str(&prop_str)?;
+        Ok(ChainProfile::from_properties(&properties))
+    }
+
+    /// An amount of planck of the chain's token
+    pub fn balance(&self, planck: u128) -> Balance {
+        Balance::new(planck, self.token_decimals)
+    }
+
+    /// An amount of planck in whole tokens, like "1,000.5000000000 DOT".
+    pub fn amount(&self, planck: u128) -> String {
+        self.balance(planck)
+            .with_symbol(&self.token_symbol)
+            .to_string()
+    }
+
+    pub fn addr(&self, account_id: &AccountId32) -> String {
+        account_id.to_ss58check_with_version(Ss58AddressFormat::custom(self.ss58_format))
+    }
+
+    /// Checks that every account has an address of this chain.
+    pub fn check_accounts(&self, accounts: &[Account]) -> Result<(), ScError> {
+        for account in accounts {
+            let (_, format) = AccountId32::from_ss58check_with_version(&account.addr)
+                .map_err(|_| ScError::InvalidPolkadotAddr(account.addr.clone()))?;
+            if format.prefix() != self.ss58_format {
+                return Err(ScError::WrongChainAddr(
+                    account.addr.clone(),
+                    self.ss58_format,
+                ));
+            }
+        }
+        Ok(())
     }
 }
 
 pub fn token_decimals(file: impl AsRef<Path>) -> Result<TokenDecimals, ScError> {
-    let prop_str =
-        fs::read_to_string(file).with_context(|| "could not open polkadot properties file")?;
-    let mut polkadot_properties: serde_json::Value = serde_json::from_str(&prop_str)?;
-    Ok(polkadot_properties["tokenDecimals"]
-        .take()
-        .as_u64()
-        .unwrap_or(0) as TokenDecimals)
+    Ok(ChainProfile::from_file(file)?.token_decimals)
 }
 
-#[derive(Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
 pub struct StakeChange {
     #[serde(deserialize_with = "util::naive_date_time_from_str")]
     pub timestamp: NaiveDateTime,
-    #[serde(deserialize_with = "util::balance_from_maybe_str")]
+    #[serde(deserialize_with = "util::balance_field::accumulated_amount")]
     #[serde(rename(deserialize = "accumulatedAmount"))]
-    pub accumulated_amount: u128,
+    pub accumulated_amount: Balance,
+    /// The indexer's node id. Missing in lines written by older versions.
+    #[serde(default)]
+    pub id: Option<String>,
 }
 
-#[derive(Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
 pub struct Reward {
     #[serde(deserialize_with = "util::naive_date_time_from_str")]
     #[serde(alias = "timestamp")]
     pub date: NaiveDateTime,
-    #[serde(deserialize_with = "util::balance_from_maybe_str")]
+    #[serde(deserialize_with = "util::balance_field::balance")]
     #[serde(alias = "amount")]
-    pub balance: u128,
+    pub balance: Balance,
+    /// The indexer's node id. Missing in lines written by older versions.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// Records we fetch from an indexer and keep in a known file.
+trait KnownRecord {
+    fn id(&self) -> Option<&str>;
+    fn content_key(&self) -> String;
+}
+
+impl KnownRecord for Reward {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+    fn content_key(&self) -> String {
+        format!("{:?},{}", self.date, self.balance.planck)
+    }
+}
+
+impl KnownRecord for StakeChange {
+    fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+    fn content_key(&self) -> String {
+        format!("{:?},{}", self.timestamp, self.accumulated_amount.planck)
+    }
+}
+
+/// Keeps the records of `latest` that are not in `olds`.
+/// Records are identified by id. Old records without id are matched by
+/// timestamp and amount instead.
+fn drop_known<T: KnownRecord>(olds: &[T], latest: Vec<T>) -> Vec<T> {
+    let mut known_ids: HashSet<String> = HashSet::new();
+    let mut known_contents: HashSet<String> = HashSet::new();
+    for old in olds {
+        match old.id() {
+            Some(id) => known_ids.insert(id.into()),
+            None => known_contents.insert(old.content_key()),
+        };
+    }
+    latest
+        .into_iter()
+        .filter(|new| {
+            let is_known = match new.id() {
+                Some(id) => !known_ids.insert(id.into()),
+                None => false,
+            };
+            !is_known && !known_contents.contains(&new.content_key())
+        })
+        .collect()
 }
 
 impl poloto::build::unwrapper::Unwrapper for Reward {
     type Item = (NaiveDateTime, u128);
     fn unwrap(self) -> (NaiveDateTime, u128) {
-        (self.date, self.balance)
+        (self.date, self.balance.planck)
     }
 }
 
@@ -190,190 +524,608 @@ pub fn known_rewards(file: impl AsRef<Path>) -> Result<Vec<Reward>, ScError> {
 }
 
 pub async fn get_stake_changes(
-    subquery_endpoint: &str,
+    subquery_endpoint: SubqueryEndpoint,
     polkadot_addr: &str,
     known_stake_changes_file: impl AsRef<Path>,
+    since: Option<NaiveDateTime>,
 ) -> Result<Vec<StakeChange>, ScError> {
     let olds = known_stake_changes(known_stake_changes_file)?;
-    let latest = query_stake_changes(subquery_endpoint, polkadot_addr).await?;
-
-    if let Some(newest_old) = olds.last() {
-        let mut latest_iter = latest.into_iter();
-        let mut news: Vec<StakeChange> = vec![];
-
-        if latest_iter.any(|x| x.timestamp >= newest_old.timestamp) {
-            for elem in latest_iter {
-                news.push(elem);
-            }
-        }
-        return Ok(news);
-    }
-    Ok(latest)
+    fetch_new_stake_changes(subquery_endpoint, polkadot_addr, &olds, since).await
 }
 
 pub async fn get_staking_rewards(
     subquery_endpoint: SubqueryEndpoint,
     polkadot_addr: &str,
     known_rewards_file: impl AsRef<Path>,
+    since: Option<NaiveDateTime>,
 ) -> Result<Vec<Reward>, ScError> {
     let olds = known_rewards(known_rewards_file)?;
-    let latest = query_staking_rewards(subquery_endpoint, polkadot_addr).await?;
+    fetch_new_staking_rewards(subquery_endpoint, polkadot_addr, &olds, since).await
+}
 
-    if let Some(newest_old) = olds.last() {
-        let mut latest_iter = latest.into_iter();
-        let mut news: Vec<Reward> = vec![];
+/// Stake changes that the store does not know about yet.
+pub async fn new_stake_changes(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    store: &dyn Store,
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<StakeChange>, ScError> {
+    let olds = store.stake_changes()?;
+    let since = since.max(store.sync_cursor(SyncCursor::StakeChanges)?);
+    fetch_new_stake_changes(subquery_endpoint, polkadot_addr, &olds, since).await
+}
 
-        if latest_iter.any(|x| x.date >= newest_old.date) {
-            for elem in latest_iter {
-                news.push(elem);
-            }
+/// Staking rewards that the store does not know about yet.
+pub async fn new_staking_rewards(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    store: &dyn Store,
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<Reward>, ScError> {
+    let olds = store.rewards()?;
+    let since = since.max(store.sync_cursor(SyncCursor::Rewards)?);
+    fetch_new_staking_rewards(subquery_endpoint, polkadot_addr, &olds, since).await
+}
+
+/// Nomination pool rewards that the store does not know about yet.
+pub async fn new_pool_rewards(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    store: &dyn Store,
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<Reward>, ScError> {
+    let olds = store.pool_rewards()?;
+    let since = since
+        .max(store.sync_cursor(SyncCursor::PoolRewards)?)
+        .max(olds.last().map(|old| old.date));
+    let latest = query_pool_rewards(subquery_endpoint, polkadot_addr, since).await?;
+    Ok(drop_known(&olds, latest))
+}
+
+async fn fetch_new_stake_changes(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    olds: &[StakeChange],
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<StakeChange>, ScError> {
+    // No need to fetch what we already know
+    let since = since.max(olds.last().map(|old| old.timestamp));
+    let latest = query_stake_changes(subquery_endpoint, polkadot_addr, since).await?;
+    Ok(drop_known(olds, latest))
+}
+
+async fn fetch_new_staking_rewards(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    olds: &[Reward],
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<Reward>, ScError> {
+    // No need to fetch what we already know
+    let since = since.max(olds.last().map(|old| old.date));
+    let latest = query_staking_rewards(subquery_endpoint, polkadot_addr, since).await?;
+    Ok(drop_known(olds, latest))
+}
+
+/// Reads a date like 2023-01-01T00:00:00, or a day like 2023-01-01, which starts at midnight.
+pub fn parse_date(date: &str) -> Result<NaiveDateTime, ScError> {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|day| day.and_hms(0, 0, 0))
+        })
+        .map_err(|_| ScError::InvalidDate(date.into()))
+}
+
+/// Rewards from `from` on, and before `to`.
+pub fn rewards_between(
+    rewards: Vec<Reward>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+) -> Vec<Reward> {
+    rewards
+        .into_iter()
+        .filter(|r| from.is_none_or(|from| r.date >= from) && to.is_none_or(|to| r.date < to))
+        .collect()
+}
+
+/// Stake changes from `from` on, and before `to`.
+/// The stake held at `from` is kept as a change at `from`, so the range starts with a stake.
+pub fn stake_changes_between(
+    stake_changes: Vec<StakeChange>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+) -> Vec<StakeChange> {
+    let mut held: Option<StakeChange> = None;
+    let mut between = vec![];
+    for change in stake_changes {
+        match from {
+            Some(from) if change.timestamp < from => held = Some(change),
+            _ if to.is_some_and(|to| change.timestamp >= to) => break,
+            _ => between.push(change),
         }
-        return Ok(news);
     }
-    Ok(latest)
+    match (held, from) {
+        (Some(held), Some(from)) if between.first().is_none_or(|c| c.timestamp > from) => {
+            between.insert(
+                0,
+                StakeChange {
+                    timestamp: from,
+                    id: None,
+                    ..held
+                },
+            );
+        }
+        _ => (),
+    }
+    between
+}
+
+/// Rewards of several accounts as one series, oldest first.
+pub fn aggregate_rewards(per_account: Vec<Vec<Reward>>) -> Vec<Reward> {
+    let mut rewards: Vec<Reward> = per_account.into_iter().flatten().collect();
+    rewards.sort_by_key(|reward| reward.date);
+    rewards
+}
+
+/// Stake of several accounts as one series, oldest first.
+/// Each stake change holds its own account's total,
+/// so the aggregated total is summed over every account's latest change.
+pub fn aggregate_stake_changes(per_account: Vec<Vec<StakeChange>>) -> Vec<StakeChange> {
+    if per_account.len() == 1 {
+        return per_account.into_iter().flatten().collect();
+    }
+    let mut changes: Vec<(usize, StakeChange)> = per_account
+        .into_iter()
+        .enumerate()
+        .flat_map(|(account, changes)| changes.into_iter().map(move |change| (account, change)))
+        .collect();
+    changes.sort_by_key(|(_, change)| change.timestamp);
+
+    let mut latest: HashMap<usize, u128> = HashMap::new();
+    changes
+        .into_iter()
+        .map(|(account, change)| {
+            latest.insert(account, change.accumulated_amount.planck);
+            StakeChange {
+                timestamp: change.timestamp,
+                accumulated_amount: Balance::new(
+                    latest.values().sum(),
+                    change.accumulated_amount.decimals,
+                ),
+                id: None,
+            }
+        })
+        .collect()
+}
+
+/// Appends records to a known file and returns how many were added.
+/// The merged file is written under a temporary name and renamed into place,
+/// so an interrupted run leaves the known file as it was.
+pub fn append_to_known_file<T: fmt::Display>(
+    file: impl AsRef<Path>,
+    news: &[T],
+) -> Result<usize, ScError> {
+    let file = file.as_ref();
+    let mut merged = match file.try_exists() {
+        Ok(true) => fs::read_to_string(file)?,
+        _ => String::new(),
+    };
+    if news.is_empty() {
+        return Ok(0);
+    }
+    if !merged.is_empty() && !merged.ends_with('\n') {
+        merged.push('\n');
+    }
+    for new in news {
+        merged += &new.to_string();
+        merged.push('\n');
+    }
+    let mut tmp_file = file.as_os_str().to_owned();
+    tmp_file.push(".tmp");
+    fs::write(&tmp_file, merged)?;
+    fs::rename(&tmp_file, file)?;
+    Ok(news.len())
+}
+
+/// Fetches new stake changes into the store.
+pub async fn sync_stake_changes(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    store: &mut dyn Store,
+    since: Option<NaiveDateTime>,
+) -> Result<usize, ScError> {
+    let news = new_stake_changes(subquery_endpoint, polkadot_addr, store, since).await?;
+    let added = store.add_stake_changes(&news)?;
+    if let Some(newest) = news.iter().map(|new| new.timestamp).max() {
+        store.set_sync_cursor(SyncCursor::StakeChanges, newest)?;
+    }
+    Ok(added)
+}
+
+/// Fetches new staking rewards into the store.
+pub async fn sync_staking_rewards(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    store: &mut dyn Store,
+    since: Option<NaiveDateTime>,
+) -> Result<usize, ScError> {
+    let news = new_staking_rewards(subquery_endpoint, polkadot_addr, store, since).await?;
+    let added = store.add_rewards(&news)?;
+    if let Some(newest) = news.iter().map(|new| new.date).max() {
+        store.set_sync_cursor(SyncCursor::Rewards, newest)?;
+    }
+    Ok(added)
+}
+
+/// Fetches new nomination pool rewards into the store.
+pub async fn sync_pool_rewards(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    store: &mut dyn Store,
+    since: Option<NaiveDateTime>,
+) -> Result<usize, ScError> {
+    let news = new_pool_rewards(subquery_endpoint, polkadot_addr, store, since).await?;
+    let added = store.add_pool_rewards(&news)?;
+    if let Some(newest) = news.iter().map(|new| new.date).max() {
+        store.set_sync_cursor(SyncCursor::PoolRewards, newest)?;
+    }
+    Ok(added)
 }
 
 impl fmt::Display for Reward {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?},{}", self.date, self.balance)
+        write!(f, "{:?},{}", self.date, self.balance.planck)?;
+        match &self.id {
+            Some(id) => write!(f, ",{id}"),
+            None => Ok(()),
+        }
     }
 }
 
 impl fmt::Display for StakeChange {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?},{}", self.timestamp, self.accumulated_amount)
+        write!(f, "{:?},{}", self.timestamp, self.accumulated_amount.planck)?;
+        match &self.id {
+            Some(id) => write!(f, ",{id}"),
+            None => Ok(()),
+        }
     }
 }
 
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+enum RewardsSchema {
+    NovaWallet,
+    StakingSum,
+}
+
 pub struct SubqueryEndpoint {
     url: String,
-    query: (String, String),
-    query_root: String,
+    schema: RewardsSchema,
+    page_size: usize,
 }
 
 impl SubqueryEndpoint {
     pub fn new(url: String) -> Self {
-        let query = if url.contains("nova-wallet-polkadot") {
-            (
-                "{ stakeChanges (filter: {address: { equalTo: \"".into(),
-                "\" }, type: {equalTo:\"rewarded\"}} orderBy: TIMESTAMP_ASC, last: 100) { \
-                  nodes { amount timestamp }}}"
-                    .into(),
-            )
-        } else {
-            (
-                "{ stakingRewards (last: 100, orderBy: DATE_ASC, \
-                   filter: { accountId : {equalTo : \""
-                    .into(),
-                "\"}}) { nodes { balance date }}}".into(),
-            )
-        };
-        let query_root = if url.contains("nova-wallet-polkadot") {
-            "stakeChanges".into()
+        let schema = if url.contains("nova-wallet-polkadot") {
+            RewardsSchema::NovaWallet
         } else {
-            "stakingRewards".into()
+            RewardsSchema::StakingSum
         };
 
         SubqueryEndpoint {
             url,
-            query,
-            query_root,
+            schema,
+            page_size: DEFAULT_PAGE_SIZE,
         }
     }
 
-    pub fn get_query(&self, polkadot_addr: &str) -> String {
-        self.query.0.clone() + polkadot_addr + &self.query.1
+    /// Number of nodes to ask for per request.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    fn query_root(&self) -> &'static str {
+        match self.schema {
+            RewardsSchema::NovaWallet => "stakeChanges",
+            RewardsSchema::StakingSum => "stakingRewards",
+        }
+    }
+
+    fn page_args(&self, after: Option<&str>) -> String {
+        match after {
+            Some(cursor) => format!("first: {}, after: \"{cursor}\"", self.page_size),
+            None => format!("first: {}", self.page_size),
+        }
+    }
+
+    /// Query for one page of rewards, starting after the `after` cursor.
+    pub fn get_query(
+        &self,
+        polkadot_addr: &str,
+        since: Option<NaiveDateTime>,
+        after: Option<&str>,
+    ) -> String {
+        let page_args = self.page_args(after);
+        let page_info = "pageInfo { hasNextPage endCursor }";
+        match self.schema {
+            RewardsSchema::NovaWallet => {
+                let since_filter = since
+                    .map(|date| {
+                        format!(
+                            ", timestamp: {{greaterThanOrEqualTo: \"{}\"}}",
+                            date.timestamp()
+                        )
+                    })
+                    .unwrap_or_default();
+                format!(
+                    "{{ stakeChanges ({page_args}, orderBy: TIMESTAMP_ASC, \
+                       filter: {{address: {{ equalTo: \"{polkadot_addr}\" }}, \
+                       type: {{equalTo:\"rewarded\"}}{since_filter}}}) {{ \
+                      nodes {{ id amount timestamp }} {page_info} }}}}"
+                )
+            }
+            RewardsSchema::StakingSum => {
+                let since_filter = since
+                    .map(|date| format!(", date: {{greaterThanOrEqualTo: \"{date:?}\"}}"))
+                    .unwrap_or_default();
+                format!(
+                    "{{ stakingRewards ({page_args}, orderBy: DATE_ASC, \
+                       filter: {{ accountId : {{equalTo : \"{polkadot_addr}\"}}{since_filter}}}) {{ \
+                      nodes {{ id balance date }} {page_info} }}}}"
+                )
+            }
+        }
+    }
+
+    /// Pool rewards are indexed like Nova Wallet's AccountPoolReward entities
+    fn pool_rewards_query(
+        &self,
+        polkadot_addr: &str,
+        since: Option<NaiveDateTime>,
+        after: Option<&str>,
+    ) -> String {
+        let since_filter = since
+            .map(|date| {
+                format!(
+                    ", timestamp: {{greaterThanOrEqualTo: \"{}\"}}",
+                    date.timestamp()
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            "{{ accountPoolRewards ({}, orderBy: TIMESTAMP_ASC, filter: \
+                {{address: {{equalTo: \"{polkadot_addr}\"}}, type: {{equalTo: reward}}{since_filter}}}) {{ \
+                  nodes {{ id amount timestamp }} \
+                  pageInfo {{ hasNextPage endCursor }}}}}}",
+            self.page_args(after)
+        )
+    }
+
+    fn stake_changes_query(
+        &self,
+        polkadot_addr: &str,
+        since: Option<NaiveDateTime>,
+        after: Option<&str>,
+    ) -> String {
+        let since_filter = since
+            .map(|date| {
+                format!(
+                    ", timestamp: {{greaterThanOrEqualTo: \"{}\"}}",
+                    date.timestamp()
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            "{{ stakeChanges ({}, orderBy: TIMESTAMP_ASC, filter: \
+                {{address : {{equalTo: \"{}\"}}{}}}) {{\
+                  nodes {{ \
+                    id \
+                    timestamp \
+                    accumulatedAmount \
+                  }} \
+                  pageInfo {{ hasNextPage endCursor }}}}}}",
+            self.page_args(after),
+            polkadot_addr,
+            since_filter
+        )
     }
 }
 
 async fn query_staking_rewards(
     subquery_endpoint: SubqueryEndpoint,
     polkadot_addr: &str,
+    since: Option<NaiveDateTime>,
 ) -> Result<Vec<Reward>, ScError> {
-    let query = subquery_endpoint.get_query(polkadot_addr);
+    let nodes = util::subquery_pages(
+        &subquery_endpoint.url,
+        subquery_endpoint.query_root(),
+        |after| subquery_endpoint.get_query(polkadot_addr, since, after),
+    )
+    .await?;
 
-    let ans = util::subquery(&subquery_endpoint.url, query).await?;
-    let maybe_rewards = ans[subquery_endpoint.query_root]["nodes"].as_array();
+    let mut ret_rewards: Vec<Reward> = Vec::new();
+    for reward in nodes {
+        let r: Reward = serde_json::from_value(reward)?;
+        ret_rewards.push(r);
+    }
+    Ok(ret_rewards)
+}
 
-    if let Some(vec) = maybe_rewards {
-        let mut ret_rewards: Vec<Reward> = Vec::new();
-        for reward in vec {
-            let r: Reward = serde_json::from_value(reward.clone())?;
-            ret_rewards.push(r);
-        }
-        return Ok(ret_rewards);
-    };
+async fn query_pool_rewards(
+    subquery_endpoint: SubqueryEndpoint,
+    polkadot_addr: &str,
+    since: Option<NaiveDateTime>,
+) -> Result<Vec<Reward>, ScError> {
+    let nodes = util::subquery_pages(&subquery_endpoint.url, "accountPoolRewards", |after| {
+        subquery_endpoint.pool_rewards_query(polkadot_addr, since, after)
+    })
+    .await?;
 
-    Ok(vec![])
+    let mut ret_rewards: Vec<Reward> = Vec::new();
+    for reward in nodes {
+        ret_rewards.push(serde_json::from_value(reward)?);
+    }
+    Ok(ret_rewards)
 }
 
 async fn query_stake_changes(
-    subquery_endpoint: &str,
+    subquery_endpoint: SubqueryEndpoint,
     polkadot_addr: &str,
+    since: Option<NaiveDateTime>,
 ) -> Result<Vec<StakeChange>, ScError> {
-    let query = format!(
-        "{{ stakeChanges (last: 100, orderBy: TIMESTAMP_ASC, filter: \
-            {{address : {{equalTo: \"{}\"}}}}) {{\
-              nodes {{ \
-                timestamp \
-                accumulatedAmount \
-              }}}}}}",
-        polkadot_addr
-    );
-
-    let ans = util::subquery(subquery_endpoint, query).await?;
-    let maybe_stake_changes = ans["stakeChanges"]["nodes"].as_array();
-    if let Some(vec) = maybe_stake_changes {
-        let mut ret_stake_changes: Vec<StakeChange> = vec![];
-        for stake_change in vec {
-            let r: StakeChange = serde_json::from_value(stake_change.clone())?;
-            ret_stake_changes.push(r);
-        }
-        return Ok(ret_stake_changes);
-    };
+    let nodes = util::subquery_pages(&subquery_endpoint.url, "stakeChanges", |after| {
+        subquery_endpoint.stake_changes_query(polkadot_addr, since, after)
+    })
+    .await?;
 
-    Ok(vec![])
+    let mut ret_stake_changes: Vec<StakeChange> = vec![];
+    for stake_change in nodes {
+        let r: StakeChange = serde_json::from_value(stake_change)?;
+        ret_stake_changes.push(r);
+    }
+    Ok(ret_stake_changes)
 }
 
-pub async fn rpc_methods(rpc_endpoint: &str) -> Result<(), ScError> {
-    let ans = util::rpc(rpc_endpoint, "rpc_methods", ()).await?;
+pub async fn rpc_methods(rpc: &RpcClient) -> Result<(), ScError> {
+    let ans = rpc.request("rpc_methods", ()).await?;
     println!("{}", serde_json::to_string_pretty(&ans).unwrap());
     Ok(())
 }
 
-pub async fn state_get_metadata(rpc_endpoint: &str) -> Result<String, ScError> {
-    let res = util::rpc(rpc_endpoint, "state_getMetadata", ()).await?;
+pub async fn fetch_runtime_metadata(
+    rpc: &RpcClient,
+    at: Option<&str>,
+) -> Result<RuntimeMetadataPrefixed, ScError> {
+    let res = match at {
+        Some(block_hash) => rpc.request("state_getMetadata", (block_hash,)).await?,
+        None => rpc.request("state_getMetadata", ()).await?,
+    };
     // Decode the hex value into bytes (which are the SCALE encoded metadata details):
-    let metadata_hex = res.as_str().unwrap();
-    let metadata_bytes = hex::decode(metadata_hex.trim_start_matches("0x")).unwrap();
+    let metadata_hex = res.as_str().ok_or(ScError::NoDataFound)?;
+    let metadata_bytes =
+        hex::decode(metadata_hex.trim_start_matches("0x")).map_err(anyhow::Error::from)?;
     // Fortunately, we know what type the metadata is, so we are able to decode our SCALEd bytes to it:
-    let decoded = RuntimeMetadataPrefixed::decode(&mut metadata_bytes.as_slice()).unwrap();
-    Ok(serde_json::to_string_pretty(&decoded).unwrap())
+    Ok(RuntimeMetadataPrefixed::decode(
+        &mut metadata_bytes.as_slice(),
+    )?)
+}
+
+pub async fn state_get_metadata(rpc: &RpcClient) -> Result<String, ScError> {
+    let decoded = fetch_runtime_metadata(rpc, None).await?;
+    Ok(serde_json::to_string_pretty(&decoded)?)
 }
 
-pub async fn system_properties(rpc_endpoint: &str) -> Result<String, ScError> {
-    let res = util::rpc(rpc_endpoint, "system_properties", ()).await?;
+/// Fetches V14 or V15 runtime metadata. Older versions are not supported.
+pub async fn fetch_metadata(rpc: &RpcClient, at: Option<&str>) -> Result<Metadata, ScError> {
+    fetch_runtime_metadata(rpc, at).await?.try_into()
+}
+
+pub async fn system_properties(rpc: &RpcClient) -> Result<String, ScError> {
+    let res = rpc.request("system_properties", ()).await?;
     Ok(serde_json::to_string_pretty(&res).unwrap())
 }
 
+pub async fn block_hash(rpc: &RpcClient, number: u32) -> Result<String, ScError> {
+    let hash = rpc.request("chain_getBlockHash", (number,)).await?;
+    match hash.as_str() {
+        Some(hash) => Ok(hash.into()),
+        None => Err(ScError::UnknownBlock(number.to_string())),
+    }
+}
+
+/// Resolves a block number or a block hash into a block hash.
+pub async fn resolve_block_hash(rpc: &RpcClient, block: &str) -> Result<String, ScError> {
+    if block.starts_with("0x") {
+        return Ok(block.into());
+    }
+    let number: u32 = block
+        .parse()
+        .map_err(|_| ScError::UnknownBlock(block.into()))?;
+    block_hash(rpc, number).await
+}
+
+pub async fn latest_block_number(rpc: &RpcClient) -> Result<u32, ScError> {
+    let header = rpc.request("chain_getHeader", ()).await?;
+    let number_hex = header["number"].as_str().ok_or(ScError::NoDataFound)?;
+    let number = u32::from_str_radix(number_hex.trim_start_matches("0x"), 16)
+        .map_err(|_| ScError::UnknownBlock(number_hex.into()))?;
+    Ok(number)
+}
+
+/// Milliseconds since the Unix epoch, as set by the block's timestamp inherent.
+pub async fn block_timestamp_millis(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    block_hash: &str,
+) -> Result<u64, ScError> {
+    let result_bytes =
+        state_get_storage(rpc, metadata, "Timestamp", "Now", &[], Some(block_hash)).await?;
+    let millis = u64::decode(&mut result_bytes.as_slice())?;
+    Ok(millis)
+}
+
+/// The spec version of the runtime a block was made with, which every runtime upgrade raises.
+pub async fn runtime_spec_version(rpc: &RpcClient, block_hash: &str) -> Result<u32, ScError> {
+    let version = rpc
+        .request("state_getRuntimeVersion", (block_hash,))
+        .await?;
+    let spec_version = version["specVersion"]
+        .as_u64()
+        .ok_or(ScError::NoDataFound)?;
+    Ok(spec_version as u32)
+}
+
+/// Binary searches for the last block with a timestamp before `date`.
+/// Returns that block's number and hash.
+pub async fn block_before_date(
+    rpc: &RpcClient,
+    date: NaiveDateTime,
+) -> Result<(u32, String), ScError> {
+    let target = date.timestamp_millis();
+    // Timestamp.Now is a plain item, so the latest runtime's metadata gives its key in any block
+    let metadata = fetch_metadata(rpc, None).await?;
+    // The genesis block has no timestamp, so it's treated as older than any date
+    let mut before = 0;
+    let mut after = latest_block_number(rpc).await? + 1;
+    while after - before > 1 {
+        let mid = before + (after - before) / 2;
+        let hash = block_hash(rpc, mid).await?;
+        if (block_timestamp_millis(rpc, &metadata, &hash).await? as i64) < target {
+            before = mid;
+        } else {
+            after = mid;
+        }
+    }
+    Ok((before, block_hash(rpc, before).await?))
+}
+
+/// Reads storage at the block with hash `at`, or at the latest block if `at` is None.
+/// Reads a storage item, with its key built from the map keys as [`Metadata::storage_key`] does.
 pub async fn state_get_storage(
-    rpc_endpoint: &str,
-    module_name: &str,
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    pallet_name: &str,
     storage_name: &str,
-    polkadot_addr: Option<&str>,
+    keys: &[&str],
+    at: Option<&str>,
 ) -> Result<Vec<u8>, ScError> {
-    let mut storage_key = Vec::new();
-    storage_key.extend_from_slice(&hashing::twox_128(module_name.as_bytes()));
-    storage_key.extend_from_slice(&hashing::twox_128(storage_name.as_bytes()));
-
-    if polkadot_addr.is_some() {
-        let account_id = AccountId32::from_string(polkadot_addr.unwrap()).unwrap();
-        storage_key.extend_from_slice(&hashing::blake2_128(account_id.as_ref()));
-        storage_key.extend_from_slice(account_id.as_ref());
-    }
+    let storage_key_hex = metadata.storage_key(pallet_name, storage_name, keys)?;
+    state_get_storage_by_key(rpc, storage_key_hex, at).await
+}
 
-    let storage_key_hex = format!("0x{}", hex::encode(&storage_key));
-    let result_hex = util::rpc(rpc_endpoint, "state_getStorage", (storage_key_hex,)).await?;
+pub async fn state_get_storage_by_key(
+    rpc: &RpcClient,
+    storage_key_hex: String,
+    at: Option<&str>,
+) -> Result<Vec<u8>, ScError> {
+    let result_hex = match at {
+        Some(block_hash) => {
+            rpc.request("state_getStorage", (storage_key_hex, block_hash))
+                .await?
+        }
+        None => rpc.request("state_getStorage", (storage_key_hex,)).await?,
+    };
 
     let result_str = result_hex.as_str();
     if result_str.is_none() {
@@ -383,6 +1135,28 @@ pub async fn state_get_storage(
     Ok(result_bytes)
 }
 
+/// Calls a runtime API function, like NominationPoolsApi_pending_rewards,
+/// with SCALE encoded arguments. Returns the SCALE encoded result.
+pub async fn state_call(
+    rpc: &RpcClient,
+    function: &str,
+    encoded_args: &[u8],
+    at: Option<&str>,
+) -> Result<Vec<u8>, ScError> {
+    let args_hex = format!("0x{}", hex::encode(encoded_args));
+    let result_hex = match at {
+        Some(block_hash) => {
+            rpc.request("state_call", (function, args_hex, block_hash))
+                .await?
+        }
+        None => rpc.request("state_call", (function, args_hex)).await?,
+    };
+    let result_str = result_hex.as_str().ok_or(ScError::NoDataFound)?;
+    let result_bytes =
+        hex::decode(result_str.trim_start_matches("0x")).map_err(anyhow::Error::from)?;
+    Ok(result_bytes)
+}
+
 pub fn decode_u128(mut bytes: &[u8]) -> Result<u128, ScError> {
     let res = u128::decode(&mut bytes)?;
     Ok(res)
@@ -395,80 +1169,136 @@ pub fn stringify_encoded_u128(bytes: &[u8]) -> Result<String, ScError> {
 
 pub fn stringify_encoded_total_issuance(
     bytes: &[u8],
-    token_decimals: TokenDecimals,
+    chain: &ChainProfile,
 ) -> Result<String, ScError> {
-    let unpointed_string = stringify_encoded_u128(bytes)?;
-    Ok(format!(
-        "{} DOT",
-        (&unpointed_string).with_decimal_point(token_decimals)
-    ))
+    Ok(chain.amount(decode_u128(bytes)?))
 }
 
 pub fn stringify_encoded_system_account(
     mut bytes: &[u8],
-    decimals: TokenDecimals,
+    chain: &ChainProfile,
 ) -> Result<String, ScError> {
     let account_info = PolkadotAccountInfo::decode(&mut bytes)?;
     Ok(format!(
-        "Nonce: {}, Consumers: {}, Providers: {}, Sufficients: {}, Free: {} DOT, Reserved: {} DOT, Misc Frozen: {} DOT, Fee Frozen: {} DOT",
+        "Nonce: {}, Consumers: {}, Providers: {}, Sufficients: {}, Free: {}, Reserved: {}, Misc Frozen: {}, Fee Frozen: {}",
         account_info.nonce,
         account_info.consumers,
         account_info.providers,
         account_info.sufficients,
-        account_info.data.free.with_decimal_point(decimals),
-        account_info.data.reserved.with_decimal_point(decimals),
-        account_info.data.misc_frozen.with_decimal_point(decimals),
-        account_info.data.fee_frozen.with_decimal_point(decimals)
+        chain.amount(account_info.data.free.planck),
+        chain.amount(account_info.data.reserved.planck),
+        chain.amount(account_info.data.misc_frozen.planck),
+        chain.amount(account_info.data.fee_frozen.planck)
     ))
 }
 
-pub async fn get_total_issuance(rpc_endpoint: &str) -> Result<u128, ScError> {
-    let result_bytes = state_get_storage(rpc_endpoint, "Balances", "TotalIssuance", None).await?;
+pub fn stringify_encoded_staking_ledger(
+    mut bytes: &[u8],
+    chain: &ChainProfile,
+) -> Result<String, ScError> {
+    let ledger = StakingLedger::decode(&mut bytes)?;
+    let unlocking = ledger
+        .unlocking
+        .iter()
+        .map(|chunk| format!("{} at era {}", chain.amount(chunk.value.planck), chunk.era))
+        .collect::<Vec<_>>();
+    Ok(format!(
+        "Stash: {}, Total: {}, Active: {}, Unlocking: [{}]",
+        chain.addr(&ledger.stash),
+        chain.amount(ledger.total.planck),
+        chain.amount(ledger.active.planck),
+        unlocking.join(", ")
+    ))
+}
+
+pub fn stringify_encoded_staking_payee(
+    mut bytes: &[u8],
+    chain: &ChainProfile,
+) -> Result<String, ScError> {
+    Ok(RewardDestination::decode(&mut bytes)?.describe(chain))
+}
+
+pub fn jsonify_encoded_total_issuance(
+    bytes: &[u8],
+    chain: &ChainProfile,
+) -> Result<serde_json::Value, ScError> {
+    Ok(serde_json::to_value(Amount::new(
+        decode_u128(bytes)?,
+        chain,
+    ))?)
+}
+
+pub fn jsonify_encoded_system_account(
+    mut bytes: &[u8],
+    chain: &ChainProfile,
+) -> Result<serde_json::Value, ScError> {
+    let account_info = PolkadotAccountInfo::decode(&mut bytes)?;
+    Ok(serde_json::to_value(AccountInfo::new(
+        &account_info,
+        chain,
+    ))?)
+}
+
+pub async fn get_total_issuance(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    at: Option<&str>,
+) -> Result<u128, ScError> {
+    let result_bytes =
+        state_get_storage(rpc, metadata, "Balances", "TotalIssuance", &[], at).await?;
     let total_issued = decode_u128(result_bytes.as_slice())?;
     Ok(total_issued)
 }
 
 pub async fn get_account_info(
-    rpc_endpoint: &str,
+    rpc: &RpcClient,
+    metadata: &Metadata,
     polkadot_addr: &str,
+    at: Option<&str>,
 ) -> Result<PolkadotAccountInfo, ScError> {
-    let result_bytes =
-        state_get_storage(rpc_endpoint, "System", "Account", Some(polkadot_addr)).await?;
+    let key = account_info_key(metadata, polkadot_addr)?;
+    let result_bytes = state_get_storage_by_key(rpc, key, at).await?;
     let account_info = PolkadotAccountInfo::decode(&mut result_bytes.as_ref())?;
     Ok(account_info)
 }
 
-pub trait DecimalPointPuttable {
-    fn with_decimal_point(self, decimals: TokenDecimals) -> String;
-}
-
-impl DecimalPointPuttable for &str {
-    fn with_decimal_point(self, decimals: TokenDecimals) -> String {
-        let len = self.chars().count();
-        if len > decimals {
-            let mut count = 0;
-            self.chars()
-                .map(|c| {
-                    count += 1;
-                    if count == (len - decimals) {
-                        c.to_string() + "."
-                    } else {
-                        c.to_string()
-                    }
-                })
-                .collect::<String>()
-        } else {
-            let mut pad: String = "".into();
-            for _ in 0..=(decimals - len) {
-                pad.push('0');
-            }
-            (pad + self).with_decimal_point(decimals)
-        }
-    }
+/// Follows an account's `System.Account` storage via `state_subscribeStorage`.
+pub struct AccountInfoWatch {
+    subscription: Subscription,
 }
 
-impl DecimalPointPuttable for u128 {
-    fn with_decimal_point(self, decimals: TokenDecimals) -> String {
-        (&format!("{}", self)).with_decimal_point(decimals)
+impl AccountInfoWatch {
+    /// Waits for the next change. Returns the hash of the block that made it,
+    /// along with the new account info.
+    /// The first change reported is the account's current state.
+    pub async fn next(&mut self) -> Option<Result<(String, PolkadotAccountInfo), ScError>> {
+        let change_set = self.subscription.next().await?;
+        let block = change_set["block"].as_str().unwrap_or_default().to_string();
+        // changes is a list of [key, value] pairs, and we only watch one key
+        let value = change_set["changes"][0][1].as_str();
+        let account_info = match value {
+            // A reaped account has no storage entry
+            None => Ok(PolkadotAccountInfo::default()),
+            Some(value_hex) => hex::decode(value_hex.trim_start_matches("0x"))
+                .map_err(|err| anyhow::anyhow!(err).into())
+                .and_then(|bytes| Ok(PolkadotAccountInfo::decode(&mut bytes.as_slice())?)),
+        };
+        Some(account_info.map(|info| (block, info)))
     }
 }
+
+/// The System.Account key of an address, which must be a valid one
+fn account_info_key(metadata: &Metadata, polkadot_addr: &str) -> Result<String, ScError> {
+    let account_id = staking::account_id(polkadot_addr)?;
+    metadata.storage_key("System", "Account", &[&account_id.to_ss58check()])
+}
+
+pub async fn watch_account_info(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    polkadot_addr: &str,
+) -> Result<AccountInfoWatch, ScError> {
+    let key = account_info_key(metadata, polkadot_addr)?;
+    let subscription = rpc.subscribe("state_subscribeStorage", ([key],)).await?;
+    Ok(AccountInfoWatch { subscription })
+}
@@ -1,47 +1,892 @@
-use clap::{AppSettings, Arg, Command};
+use chrono::NaiveDateTime;
+use clap::{AppSettings, Arg, ArgMatches, Command};
 use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 
-use sp_core::crypto::{AccountId32, Ss58AddressFormatRegistry, Ss58Codec};
-
 use stake_checker::*;
 
-fn get_valid_env_var(var_name: &str) -> Result<String, ScError> {
-    let var = match dotenv::var(&var_name) {
-        Ok(s) => s,
-        Err(_) => "".into(),
+fn account_balances_string(account_info: &PolkadotAccountInfo, chain: &ChainProfile) -> String {
+    format!(
+        "Free: {}, Reserved: {}, Misc Frozen: {}, Fee Frozen: {}",
+        chain.amount(account_info.data.free.planck),
+        chain.amount(account_info.data.reserved.planck),
+        chain.amount(account_info.data.misc_frozen.planck),
+        chain.amount(account_info.data.fee_frozen.planck)
+    )
+}
+
+fn account_arg() -> Arg<'static> {
+    Arg::with_name("account")
+        .long("account")
+        .takes_value(true)
+        .value_name("name")
+        .help(
+            "Run for one of the accounts named in the profile or in POLKADOT_ACCOUNTS. \
+            All accounts are used if omitted.",
+        )
+}
+
+fn at_args() -> [Arg<'static>; 2] {
+    [
+        Arg::with_name("at")
+            .long("at")
+            .takes_value(true)
+            .value_name("block")
+            .help(
+                "Block number or block hash to read state at. \
+                Latest block is used if omitted.",
+            ),
+        Arg::with_name("date")
+            .long("date")
+            .takes_value(true)
+            .value_name("date")
+            .conflicts_with("at")
+            .help("Read state at the last block before a date, like 2023-01-01T00:00:00"),
+    ]
+}
+
+fn since_arg() -> Arg<'static> {
+    Arg::with_name("since")
+        .long("since")
+        .takes_value(true)
+        .value_name("date")
+        .help("Only get data from this date on, like 2023-01-01T00:00:00")
+}
+
+/// A command's view of the settings.
+/// Settings are resolved as the command asks for them,
+/// so a command only requires the settings it uses.
+struct Context<'a> {
+    command: &'a str,
+    matches: &'a ArgMatches,
+    profile: Profile,
+    output: OutputFormat,
+}
+
+impl Context<'_> {
+    /// Tells which command needed a missing setting
+    fn needs<T>(&self, setting: Result<T, ScError>) -> Result<T, ScError> {
+        setting.map_err(|err| match err {
+            ScError::MissingEnvVariable(var) => ScError::MissingSetting(var, self.command.into()),
+            err => err,
+        })
+    }
+
+    fn rpc(&self) -> Result<RpcClient, ScError> {
+        Ok(RpcClient::new(&self.needs(self.profile.rpc_endpoint())?))
+    }
+
+    /// Fetches the chain's system properties on first use
+    async fn chain(&self, rpc: &RpcClient) -> Result<ChainProfile, ScError> {
+        let polkadot_properties_file = self.profile.polkadot_properties_file();
+        match Path::new(&polkadot_properties_file).try_exists() {
+            Ok(true) => (),
+            _ => {
+                eprintln!("Couldn't find {polkadot_properties_file}. Creating and populating it.");
+                let polka_props = system_properties(rpc).await?;
+                fs::write(&polkadot_properties_file, polka_props)?;
+            }
+        };
+        ChainProfile::from_file(polkadot_properties_file)
+    }
+
+    fn accounts(&self) -> Result<Vec<Account>, ScError> {
+        let accounts = self.needs(self.profile.accounts())?;
+        select_accounts(accounts, self.matches.value_of("account"))
+    }
+
+    fn stake_changes_endpoint(&self) -> Result<SubqueryEndpoint, ScError> {
+        let url = self.needs(self.profile.subquery_endpoint_stake_changes())?;
+        Ok(SubqueryEndpoint::new(url).with_page_size(self.profile.subquery_page_size()?))
+    }
+
+    fn rewards_endpoint(&self) -> Result<SubqueryEndpoint, ScError> {
+        let url = self.needs(self.profile.subquery_endpoint_rewards())?;
+        Ok(SubqueryEndpoint::new(url).with_page_size(self.profile.subquery_page_size()?))
+    }
+
+    /// None if no endpoint is set for pool rewards
+    fn pool_rewards_endpoint(&self) -> Result<Option<SubqueryEndpoint>, ScError> {
+        let page_size = self.profile.subquery_page_size()?;
+        Ok(self
+            .profile
+            .subquery_endpoint_pool_rewards()
+            .map(|url| SubqueryEndpoint::new(url).with_page_size(page_size)))
+    }
+
+    fn since(&self) -> Result<Option<NaiveDateTime>, ScError> {
+        self.matches.value_of("since").map(parse_date).transpose()
+    }
+
+    async fn at(&self, rpc: &RpcClient) -> Result<Option<String>, ScError> {
+        Ok(
+            match (self.matches.value_of("at"), self.matches.value_of("date")) {
+                (Some(block), _) => Some(resolve_block_hash(rpc, block).await?),
+                (None, Some(date)) => {
+                    let (number, hash) = block_before_date(rpc, parse_date(date)?).await?;
+                    eprintln!("Reading state at block {number} ({hash})");
+                    Some(hash)
+                }
+                (None, None) => None,
+            },
+        )
+    }
+}
+
+/// Account names prefix the output when it mixes several accounts
+fn prefix(accounts: &[Account], account: &Account) -> String {
+    match accounts.len() {
+        1 => String::new(),
+        _ => format!("{},", account.name),
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Synced {
+    stake_changes: usize,
+    rewards: usize,
+    pool_rewards: usize,
+}
+
+async fn sync(cx: &Context<'_>) -> Result<(), ScError> {
+    let since = cx.since()?;
+    let mut synced = vec![];
+    for account in &cx.accounts()? {
+        let mut store = cx.profile.store_for_account(account)?;
+        let sc = cx.stake_changes_endpoint()?;
+        let stake_changes = sync_stake_changes(sc, &account.addr, store.as_mut(), since).await?;
+        let sr = cx.rewards_endpoint()?;
+        let rewards = sync_staking_rewards(sr, &account.addr, store.as_mut(), since).await?;
+        let pool_rewards = match cx.pool_rewards_endpoint()? {
+            Some(pr) => sync_pool_rewards(pr, &account.addr, store.as_mut(), since).await?,
+            None => 0,
+        };
+        if cx.output == OutputFormat::Table {
+            println!("Added {stake_changes} stake changes for {}", account.name);
+            println!("Added {rewards} staking rewards for {}", account.name);
+            if cx.profile.subquery_endpoint_pool_rewards().is_some() {
+                println!("Added {pool_rewards} pool rewards for {}", account.name);
+            }
+        }
+        let counts = Synced {
+            stake_changes,
+            rewards,
+            pool_rewards,
+        };
+        synced.push(OfAccount::new(&account.name, counts));
+    }
+    match cx.output {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => print_json(&synced),
+        OutputFormat::Csv => print_csv(
+            &["account", "stake_changes", "rewards", "pool_rewards"],
+            synced.iter().map(|s| {
+                [
+                    s.account.clone(),
+                    s.record.stake_changes.to_string(),
+                    s.record.rewards.to_string(),
+                    s.record.pool_rewards.to_string(),
+                ]
+            }),
+        ),
+    }
+}
+
+async fn stake_changes(cx: &Context<'_>) -> Result<(), ScError> {
+    let since = cx.since()?;
+    let accounts = cx.accounts()?;
+    let mut all_stake_changes = vec![];
+    for account in &accounts {
+        let store = cx.profile.store_for_account(account)?;
+        let sc = cx.stake_changes_endpoint()?;
+        let stake_changes = new_stake_changes(sc, &account.addr, store.as_ref(), since).await?;
+        if cx.output == OutputFormat::Table {
+            print!(
+                "{}",
+                stake_changes.iter().fold(String::new(), |acc, c| acc
+                    + &prefix(&accounts, account)
+                    + &c.to_string()
+                    + "\n")
+            );
+        }
+        all_stake_changes.extend(
+            stake_changes
+                .into_iter()
+                .map(|c| OfAccount::new(&account.name, c)),
+        );
+    }
+    match cx.output {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => print_json(&all_stake_changes),
+        OutputFormat::Csv => print_csv(
+            &["account", "timestamp", "accumulated_amount", "id"],
+            all_stake_changes.iter().map(|c| {
+                [
+                    c.account.clone(),
+                    format!("{:?}", c.record.timestamp),
+                    c.record.accumulated_amount.planck.to_string(),
+                    c.record.id.clone().unwrap_or_default(),
+                ]
+            }),
+        ),
+    }
+}
+
+async fn staking_rewards(cx: &Context<'_>) -> Result<(), ScError> {
+    let since = cx.since()?;
+    let accounts = cx.accounts()?;
+    let mut all_rewards = vec![];
+    for account in &accounts {
+        let store = cx.profile.store_for_account(account)?;
+        let sr = cx.rewards_endpoint()?;
+        let staking_rewards = new_staking_rewards(sr, &account.addr, store.as_ref(), since).await?;
+        if cx.output == OutputFormat::Table {
+            print!(
+                "{}",
+                staking_rewards.iter().fold(String::new(), |acc, r| acc
+                    + &prefix(&accounts, account)
+                    + &r.to_string()
+                    + "\n")
+            );
+        }
+        all_rewards.extend(
+            staking_rewards
+                .into_iter()
+                .map(|r| OfAccount::new(&account.name, r)),
+        );
+    }
+    match cx.output {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => print_json(&all_rewards),
+        OutputFormat::Csv => print_csv(
+            &["account", "date", "balance", "id"],
+            all_rewards.iter().map(|r| {
+                [
+                    r.account.clone(),
+                    format!("{:?}", r.record.date),
+                    r.record.balance.planck.to_string(),
+                    r.record.id.clone().unwrap_or_default(),
+                ]
+            }),
+        ),
+    }
+}
+
+/// A block given by number, or by date. A date stands for its first block when starting a range,
+/// and for the last block before it when ending one.
+async fn block_number(rpc: &RpcClient, block: &str, starts_range: bool) -> Result<u32, ScError> {
+    if let Ok(number) = block.parse() {
+        return Ok(number);
+    }
+    let (before, _) = block_before_date(rpc, parse_date(block)?).await?;
+    Ok(before + starts_range as u32)
+}
+
+#[derive(serde::Serialize)]
+struct ChainSynced {
+    rewards: usize,
+    pool_rewards: usize,
+}
+
+async fn chain_rewards(cx: &Context<'_>) -> Result<(), ScError> {
+    let accounts = cx.accounts()?;
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    chain.check_accounts(&accounts)?;
+    let from = block_number(&rpc, cx.matches.value_of("from").unwrap(), true).await?;
+    let to = match cx.matches.value_of("to") {
+        Some(to) => block_number(&rpc, to, false).await?,
+        None => latest_block_number(&rpc).await?,
     };
+    let sync = cx.matches.is_present("sync");
 
-    if var.is_empty() {
-        return Err(ScError::MissingEnvVariable(var_name.into()));
+    let mut all_rewards = vec![];
+    let mut synced = vec![];
+    for account in &accounts {
+        let rewards = get_chain_rewards(&rpc, &account.addr, from, to).await?;
+        if sync {
+            let mut store = cx.profile.store_for_account(account)?;
+            let (rewards, pool_rewards) = add_chain_rewards(store.as_mut(), &rewards)?;
+            if cx.output == OutputFormat::Table {
+                println!("Added {rewards} staking rewards for {}", account.name);
+                println!("Added {pool_rewards} pool rewards for {}", account.name);
+            }
+            let counts = ChainSynced {
+                rewards,
+                pool_rewards,
+            };
+            synced.push(OfAccount::new(&account.name, counts));
+            continue;
+        }
+        if cx.output == OutputFormat::Table {
+            for r in &rewards {
+                let source = match (r.pool_id, r.era) {
+                    (Some(pool_id), _) => format!("pool {pool_id}"),
+                    (None, Some(era)) => format!("era {era}"),
+                    (None, None) => "staking".into(),
+                };
+                println!(
+                    "{}{:?}, block {}, {source}: {}",
+                    prefix(&accounts, account),
+                    r.reward.date,
+                    r.block,
+                    chain.amount(r.reward.balance.planck)
+                );
+            }
+        }
+        all_rewards.extend(
+            rewards
+                .into_iter()
+                .map(|r| OfAccount::new(&account.name, r)),
+        );
+    }
+    match (cx.output, sync) {
+        (OutputFormat::Table, _) => Ok(()),
+        (OutputFormat::Json, true) => print_json(&synced),
+        (OutputFormat::Csv, true) => print_csv(
+            &["account", "rewards", "pool_rewards"],
+            synced.iter().map(|s| {
+                [
+                    s.account.clone(),
+                    s.record.rewards.to_string(),
+                    s.record.pool_rewards.to_string(),
+                ]
+            }),
+        ),
+        (OutputFormat::Json, false) => print_json(&all_rewards),
+        (OutputFormat::Csv, false) => print_csv(
+            &[
+                "account", "block", "era", "pool_id", "date", "balance", "id",
+            ],
+            all_rewards.iter().map(|r| {
+                [
+                    r.account.clone(),
+                    r.record.block.to_string(),
+                    r.record.era.map(|era| era.to_string()).unwrap_or_default(),
+                    r.record
+                        .pool_id
+                        .map(|id| id.to_string())
+                        .unwrap_or_default(),
+                    format!("{:?}", r.record.reward.date),
+                    r.record.reward.balance.planck.to_string(),
+                    r.record.reward.id.clone().unwrap_or_default(),
+                ]
+            }),
+        ),
     }
+}
 
-    Ok(var)
+/// Commands that print json documents as they come from the node
+fn json_only(cx: &Context<'_>) -> Result<(), ScError> {
+    match cx.output {
+        OutputFormat::Csv => Err(ScError::UnsupportedOutput(cx.output, cx.command.into())),
+        _ => Ok(()),
+    }
 }
 
-fn valid_subquery_endpoint_stake_changes_from_env() -> Result<String, ScError> {
-    get_valid_env_var("SUBQUERY_ENDPOINT_STAKE_CHANGES")
+async fn metadata(cx: &Context<'_>) -> Result<(), ScError> {
+    json_only(cx)?;
+    let rpc = cx.rpc()?;
+    if cx.matches.is_present("pallets") {
+        let metadata = fetch_metadata(&rpc, cx.at(&rpc).await?.as_deref()).await?;
+        if cx.output == OutputFormat::Json {
+            return print_json(&metadata.pallet_summaries());
+        }
+        println!("Runtime metadata V{}", metadata.version());
+        for pallet in metadata.pallet_summaries() {
+            println!("{pallet}");
+        }
+    } else {
+        let metadata = state_get_metadata(&rpc).await?;
+        println!("{metadata}");
+    }
+    Ok(())
 }
 
-fn valid_subquery_endpoint_rewards_from_env() -> Result<String, ScError> {
-    get_valid_env_var("SUBQUERY_ENDPOINT_REWARDS")
+async fn total_issuance(cx: &Context<'_>) -> Result<(), ScError> {
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref()).await?;
+    let total_issuance = get_total_issuance(&rpc, &metadata, at.as_deref()).await?;
+    match cx.output {
+        OutputFormat::Table => {
+            println!("Total issued {}", chain.amount(total_issuance));
+            Ok(())
+        }
+        OutputFormat::Json => print_json(&Amount::new(total_issuance, &chain)),
+        OutputFormat::Csv => {
+            let amount = Amount::new(total_issuance, &chain);
+            print_csv(
+                &["planck", "tokens", "symbol"],
+                [[amount.planck, amount.tokens, amount.symbol]],
+            )
+        }
+    }
 }
 
-fn valid_rpc_endpoint_from_env() -> Result<String, ScError> {
-    get_valid_env_var("RPC_ENDPOINT")
+async fn account_balances(cx: &Context<'_>) -> Result<(), ScError> {
+    let accounts = cx.accounts()?;
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    chain.check_accounts(&accounts)?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref()).await?;
+
+    let mut balances = vec![];
+    for account in &accounts {
+        let account_info = get_account_info(&rpc, &metadata, &account.addr, at.as_deref()).await?;
+        balances.push(OfAccount::new(&account.name, account_info));
+    }
+    match cx.output {
+        OutputFormat::Table => {
+            if let [balance] = balances.as_slice() {
+                println!("{}", account_balances_string(&balance.record, &chain));
+                return Ok(());
+            }
+            let overflow = || ScError::InvalidBalance("total of the balances overflows".into());
+            let mut total = PolkadotAccountInfo::default();
+            for balance in &balances {
+                let account_info = &balance.record;
+                println!(
+                    "{}: {}",
+                    balance.account,
+                    account_balances_string(account_info, &chain)
+                );
+                let (sum, data) = (&mut total.data, &account_info.data);
+                sum.free = sum.free.checked_add(data.free).ok_or_else(overflow)?;
+                sum.reserved = sum
+                    .reserved
+                    .checked_add(data.reserved)
+                    .ok_or_else(overflow)?;
+                sum.misc_frozen = sum
+                    .misc_frozen
+                    .checked_add(data.misc_frozen)
+                    .ok_or_else(overflow)?;
+                sum.fee_frozen = sum
+                    .fee_frozen
+                    .checked_add(data.fee_frozen)
+                    .ok_or_else(overflow)?;
+            }
+            println!("Total: {}", account_balances_string(&total, &chain));
+            Ok(())
+        }
+        OutputFormat::Json => print_json(
+            &balances
+                .iter()
+                .map(|b| OfAccount::new(&b.account, AccountBalances::new(&b.record, &chain)))
+                .collect::<Vec<_>>(),
+        ),
+        OutputFormat::Csv => print_csv(
+            &["account", "free", "reserved", "misc_frozen", "fee_frozen"],
+            balances.iter().map(|b| {
+                let data = &b.record.data;
+                [
+                    b.account.clone(),
+                    data.free.planck.to_string(),
+                    data.reserved.planck.to_string(),
+                    data.misc_frozen.planck.to_string(),
+                    data.fee_frozen.planck.to_string(),
+                ]
+            }),
+        ),
+    }
 }
 
-fn valid_polkadot_addr_from_env() -> Result<String, ScError> {
-    let addr = get_valid_env_var("POLKADOT_ADDR")?;
-    let account_id =
-        AccountId32::from_string(&addr).map_err(|_| ScError::InvalidPolkadotAddr(addr.clone()))?;
-    let back_to_string =
-        account_id.to_ss58check_with_version(Ss58AddressFormatRegistry::PolkadotAccount.into());
-    if addr != back_to_string {
-        return Err(ScError::InvalidPolkadotAddr(addr));
+async fn era_apr(cx: &Context<'_>) -> Result<(), ScError> {
+    let eras: u32 = cx.matches.value_of_t_or_exit("eras");
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref()).await?;
+    let aprs = get_recent_era_aprs(&rpc, &metadata, eras, at.as_deref()).await?;
+    match cx.output {
+        OutputFormat::Table => {
+            for era_apr in &aprs {
+                let start = era_apr
+                    .start
+                    .map(|start| format!(" (from about {})", start.format("%Y-%m-%d %H:%M")))
+                    .unwrap_or_default();
+                println!(
+                    "Era {}{start}: {:.2}% APR, validator reward {}, total stake {}",
+                    era_apr.era,
+                    era_apr.apr * 100.,
+                    chain.amount(era_apr.validator_reward.planck),
+                    chain.amount(era_apr.total_stake.planck)
+                );
+            }
+            match mean_apr(&aprs) {
+                Some(mean) => println!("Mean: {:.2}% APR over {} eras", mean * 100., aprs.len()),
+                None => return Err(ScError::NoDataFound),
+            }
+            Ok(())
+        }
+        OutputFormat::Json => print_json(&aprs),
+        OutputFormat::Csv => print_csv(
+            &["era", "start", "validator_reward", "total_stake", "apr"],
+            aprs.iter().map(|a| {
+                [
+                    a.era.to_string(),
+                    a.start.map(|s| format!("{s:?}")).unwrap_or_default(),
+                    a.validator_reward.planck.to_string(),
+                    a.total_stake.planck.to_string(),
+                    a.apr.to_string(),
+                ]
+            }),
+        ),
     }
-    Ok(addr)
+}
+
+fn unbonding_string(unbonding: &Unbonding) -> String {
+    let when = match (unbonding.eras_left, unbonding.withdrawable_at) {
+        (0, _) => "withdrawable now".into(),
+        (eras_left, Some(at)) => format!(
+            "withdrawable in {eras_left} eras, about {}",
+            at.format("%Y-%m-%d %H:%M")
+        ),
+        (eras_left, None) => format!("withdrawable in {eras_left} eras"),
+    };
+    format!(
+        "Unlocking {} {} at era {}, {when}",
+        unbonding.amount.tokens, unbonding.amount.symbol, unbonding.era
+    )
+}
+
+async fn staking(cx: &Context<'_>) -> Result<(), ScError> {
+    let accounts = cx.accounts()?;
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    chain.check_accounts(&accounts)?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref()).await?;
+    let era_length = era_length_millis(&metadata)?;
+    let active_era = get_active_era(&rpc, &metadata, at.as_deref()).await?;
+    if cx.output == OutputFormat::Table {
+        println!("Active era {}", active_era.index);
+    }
+
+    let mut statuses = vec![];
+    for account in &accounts {
+        let state = get_staking_state(&rpc, &metadata, &account.addr, at.as_deref()).await?;
+        let status = StakingStatus::new(&state, &active_era, era_length, &chain);
+        if cx.output == OutputFormat::Table {
+            let prefix = prefix(&accounts, account);
+            println!(
+                "{prefix}Bonded: {}, Active: {}, Rewards to: {}, Controller: {}",
+                chain.amount(state.ledger.total.planck),
+                chain.amount(state.ledger.active.planck),
+                status.payee,
+                status.controller
+            );
+            for unbonding in &status.unlocking {
+                println!("{prefix}{}", unbonding_string(unbonding));
+            }
+        }
+        let unlocking: u128 = state.ledger.unlocking.iter().map(|u| u.value.planck).sum();
+        statuses.push((OfAccount::new(&account.name, status), unlocking));
+    }
+    match cx.output {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => print_json(&statuses.iter().map(|(s, _)| s).collect::<Vec<_>>()),
+        OutputFormat::Csv => print_csv(
+            &[
+                "account",
+                "controller",
+                "total",
+                "active",
+                "unlocking",
+                "payee",
+            ],
+            statuses.iter().map(|(s, unlocking)| {
+                [
+                    s.account.clone(),
+                    s.record.controller.clone(),
+                    s.record.total.planck.clone(),
+                    s.record.active.planck.clone(),
+                    unlocking.to_string(),
+                    s.record.payee.clone(),
+                ]
+            }),
+        ),
+    }
+}
+
+fn nominee_string(nominee: &Nominee) -> String {
+    let name = nominee.name.as_deref().unwrap_or(&nominee.validator);
+    match nominee.commission_percent {
+        None => format!("{name}: not validating"),
+        Some(commission) => format!(
+            "{name}: {commission:.2}% commission, {}{}",
+            if nominee.active { "active" } else { "inactive" },
+            if nominee.blocked { ", blocked" } else { "" }
+        ),
+    }
+}
+
+async fn nominations(cx: &Context<'_>) -> Result<(), ScError> {
+    let accounts = cx.accounts()?;
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    chain.check_accounts(&accounts)?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref()).await?;
+    let active_era = get_active_era(&rpc, &metadata, at.as_deref()).await?;
+
+    let mut statuses = vec![];
+    for account in &accounts {
+        let nominations = get_nominations(&rpc, &metadata, &account.addr, at.as_deref()).await?;
+        let targets =
+            get_nomination_targets(&rpc, &metadata, &nominations, &active_era, at.as_deref())
+                .await?;
+        let status = NominationsStatus {
+            submitted_in: nominations.submitted_in,
+            active_era: active_era.index,
+            nominees: targets.iter().map(|t| Nominee::new(t, &chain)).collect(),
+        };
+        // Nominating only inactive validators earns nothing
+        if !status.nominees.iter().any(|n| n.active) {
+            eprintln!(
+                "Warning: none of the validators nominated by {} are active in era {}",
+                account.name, active_era.index
+            );
+        }
+        if cx.output == OutputFormat::Table {
+            let prefix = prefix(&accounts, account);
+            println!(
+                "{prefix}Nominating {} validators since era {}",
+                status.nominees.len(),
+                status.submitted_in
+            );
+            for nominee in &status.nominees {
+                println!("{prefix}{}", nominee_string(nominee));
+            }
+        }
+        statuses.push(OfAccount::new(&account.name, status));
+    }
+    match cx.output {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => print_json(&statuses),
+        OutputFormat::Csv => print_csv(
+            &[
+                "account",
+                "validator",
+                "name",
+                "commission_percent",
+                "blocked",
+                "active",
+            ],
+            statuses.iter().flat_map(|s| {
+                s.record.nominees.iter().map(|n| {
+                    [
+                        s.account.clone(),
+                        n.validator.clone(),
+                        n.name.clone().unwrap_or_default(),
+                        n.commission_percent
+                            .map(|c| c.to_string())
+                            .unwrap_or_default(),
+                        n.blocked.to_string(),
+                        n.active.to_string(),
+                    ]
+                })
+            }),
+        ),
+    }
+}
+
+async fn pools(cx: &Context<'_>) -> Result<(), ScError> {
+    let accounts = cx.accounts()?;
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    chain.check_accounts(&accounts)?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref()).await?;
+    let era_length = era_length_millis(&metadata)?;
+    let active_era = get_active_era(&rpc, &metadata, at.as_deref()).await?;
+
+    let mut memberships = vec![];
+    for account in &accounts {
+        let member = get_pool_member(&rpc, &metadata, &account.addr, at.as_deref()).await?;
+        let pool = get_bonded_pool(&rpc, &metadata, member.pool_id, at.as_deref()).await?;
+        let pending = get_pending_pool_rewards(&rpc, &account.addr, at.as_deref()).await?;
+        let membership =
+            PoolMembership::new(&member, &pool, pending, &active_era, era_length, &chain);
+        if cx.output == OutputFormat::Table {
+            let prefix = prefix(&accounts, account);
+            let name = pool
+                .name
+                .as_ref()
+                .map(|name| format!(" ({name})"))
+                .unwrap_or_default();
+            let commission = pool
+                .commission_percent
+                .map(|c| format!(", {c:.2}% commission"))
+                .unwrap_or_default();
+            println!(
+                "{prefix}Pool {}{name}, {}{commission}: Points: {}, Pending rewards: {}",
+                pool.id,
+                pool.state,
+                chain.balance(member.points.planck),
+                chain.amount(pending.planck)
+            );
+            for unbonding in &membership.unbonding {
+                println!("{prefix}{}", unbonding_string(unbonding));
+            }
+        }
+        memberships.push(OfAccount::new(&account.name, membership));
+    }
+    match cx.output {
+        OutputFormat::Table => Ok(()),
+        OutputFormat::Json => print_json(&memberships),
+        OutputFormat::Csv => print_csv(
+            &[
+                "account",
+                "pool_id",
+                "pool_name",
+                "pool_state",
+                "points",
+                "pending_rewards",
+            ],
+            memberships.iter().map(|m| {
+                [
+                    m.account.clone(),
+                    m.record.pool_id.to_string(),
+                    m.record.pool_name.clone().unwrap_or_default(),
+                    m.record.pool_state.clone(),
+                    m.record.points.planck.clone(),
+                    m.record.pending_rewards.planck.clone(),
+                ]
+            }),
+        ),
+    }
+}
+
+async fn get_storage(cx: &Context<'_>) -> Result<(), ScError> {
+    json_only(cx)?;
+    let mut known_stringifiers = HashMap::<String, Stringifier>::new();
+    known_stringifiers.insert(
+        "BalancesTotalIssuance".into(),
+        stringify_encoded_total_issuance,
+    );
+    known_stringifiers.insert("SystemAccount".into(), stringify_encoded_system_account);
+    known_stringifiers.insert("StakingLedger".into(), stringify_encoded_staking_ledger);
+    known_stringifiers.insert("StakingPayee".into(), stringify_encoded_staking_payee);
+    let mut known_jsonifiers = HashMap::<String, Jsonifier>::new();
+    known_jsonifiers.insert(
+        "BalancesTotalIssuance".into(),
+        jsonify_encoded_total_issuance,
+    );
+    known_jsonifiers.insert("SystemAccount".into(), jsonify_encoded_system_account);
+    let module = cx
+        .matches
+        .value_of("module")
+        .expect("Storage module is required");
+    let name = cx
+        .matches
+        .value_of("name")
+        .expect("Storage name is required");
+    let keys: Vec<&str> = cx.matches.values_of("keys").unwrap_or_default().collect();
+
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    let at = cx.at(&rpc).await?;
+    let metadata = fetch_metadata(&rpc, at.as_deref())
+        .await?
+        .with_ss58_format(chain.ss58_format);
+    let storage_key_hex = metadata.storage_key(module, name, &keys)?;
+    let bytes = state_get_storage_by_key(&rpc, storage_key_hex, at.as_deref()).await?;
+
+    if cx.output == OutputFormat::Json {
+        // Values that can't be decoded are given as hex of their encoding
+        let value = match known_jsonifiers.get(&(module.to_owned() + name)) {
+            Some(jsonify) => jsonify(bytes.as_slice(), &chain)?,
+            None => metadata
+                .decode_storage_value(module, name, &bytes)
+                .unwrap_or_else(|_| format!("0x{}", hex::encode(&bytes)).into()),
+        };
+        return print_json(&serde_json::json!({
+            "module": module,
+            "name": name,
+            "keys": keys,
+            "value": value,
+        }));
+    }
+    match known_stringifiers.get(&(module.to_owned() + name)) {
+        Some(stringify) => {
+            let stringified = stringify(bytes.as_slice(), &chain)?;
+            println!("{stringified}");
+        }
+        None => match metadata.decode_storage_value(module, name, &bytes) {
+            Ok(decoded) => println!("{}", serde_json::to_string_pretty(&decoded)?),
+            Err(_) => println!("{:?}", bytes),
+        },
+    }
+    Ok(())
+}
+
+async fn watch(cx: &Context<'_>) -> Result<(), ScError> {
+    let accounts = cx.accounts()?;
+    let account = match accounts.as_slice() {
+        [account] => account,
+        _ => return Err(ScError::OneAccountOnly(cx.command.into())),
+    };
+    let rpc = cx.rpc()?;
+    let chain = cx.chain(&rpc).await?;
+    chain.check_accounts(&accounts)?;
+    let mut store = cx.profile.store_for_account(account)?;
+    let mut watch_file = match cx.matches.value_of("file") {
+        Some(file) => Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)?,
+        ),
+        None => None,
+    };
+    if watch_file.is_none() && cx.output == OutputFormat::Csv {
+        println!("at,block,free,reserved,misc_frozen,fee_frozen");
+    }
+    let metadata = fetch_metadata(&rpc, None).await?;
+    let mut watch = watch_account_info(&rpc, &metadata, &account.addr).await?;
+    let mut previous: Option<PolkadotAccountInfo> = None;
+    while let Some(change) = watch.next().await {
+        let (block, account_info) = change?;
+        // Nonce changes alone are not interesting
+        if previous.as_ref().map(|p| &p.data) == Some(&account_info.data) {
+            continue;
+        }
+        let snapshot = BalanceSnapshot {
+            at: chrono::Utc::now().naive_utc(),
+            block,
+            free: account_info.data.free.planck,
+            reserved: account_info.data.reserved.planck,
+            misc_frozen: account_info.data.misc_frozen.planck,
+            fee_frozen: account_info.data.fee_frozen.planck,
+        };
+        match &mut watch_file {
+            Some(file) => {
+                writeln!(file, "{snapshot}")?;
+                file.flush()?;
+            }
+            None => {
+                // Changes never end, so json is printed one line per change
+                match cx.output {
+                    OutputFormat::Table => println!(
+                        "{:?} {}: {}",
+                        snapshot.at,
+                        snapshot.block,
+                        account_balances_string(&account_info, &chain)
+                    ),
+                    OutputFormat::Json => println!("{}", serde_json::to_string(&snapshot)?),
+                    OutputFormat::Csv => println!("{snapshot}"),
+                }
+                store.add_balance_snapshot(&snapshot)?;
+            }
+        }
+        previous = Some(account_info);
+    }
+    Err(ScError::ConnectionClosed)
 }
 
 #[tokio::main]
@@ -50,195 +895,249 @@ async fn main() -> Result<(), ScError> {
         .version("1.0")
         .author("Torbjørn L. <tobben@fastmail.fm>")
         .about("Check Polkadot Staking Rewards")
-        .setting(AppSettings::ArgRequiredElseHelp)
-        .arg(
-            Arg::with_name("rpc_methods")
-                .long("rpc_methods")
-                .short('r')
-                .takes_value(false)
-                .help("Call endpoint func rpc_methods"),
-        )
-        .arg(
-            Arg::with_name("metadata")
-                .long("metadata")
-                .short('m')
-                .takes_value(false)
-                .help("Call endpoint func state_getMetadata"),
-        )
-        .arg(
-            Arg::with_name("properties")
-                .long("properties")
-                .short('p')
-                .takes_value(false)
-                .help("Call endpoint func system_properties"),
-        )
-        .arg(
-            Arg::with_name("total_issuance")
-                .long("total_issuance")
-                .short('t')
-                .takes_value(false)
-                .help("Get endpoint chain's total issuance"),
-        )
-        .arg(
-            Arg::with_name("account_balances")
-                .long("account_balances")
-                .short('a')
-                .takes_value(false)
-                .help("Get account's balances"),
-        )
+        .setting(AppSettings::SubcommandRequiredElseHelp)
         .arg(
-            Arg::with_name("get_storage")
-                .long("get_storage")
-                .short('g')
+            Arg::with_name("config")
+                .long("config")
                 .takes_value(true)
-                .multiple_values(true)
-                .min_values(2)
-                .max_values(3)
+                .value_name("file")
+                .global(true)
                 .help(
-                    "Raw state_getStorage rpc call. Provide at least two args: <method>, \
-                    and <name>. Third is optional. The program will try to decode the value \
-                    before printing, but will print raw bytes if the method+name combination \
-                    is unknown.",
+                    "Config file to read profiles from. Defaults to stake-checker.toml \
+                    in the working directory, or else stake-checker/config.toml \
+                    in the XDG config directory. Settings in .env override the profile's.",
                 ),
         )
         .arg(
-            Arg::with_name("stake_changes")
-                .long("stake_changes")
-                .short('c')
-                .takes_value(false)
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .value_name("format")
+                .possible_values(OutputFormat::NAMES)
+                .default_value("table")
+                .global(true)
                 .help(
-                    "Get account's stake changes. \
-                       Will skip those already listed in \
-                       known stake changes file listen in .env. \
-                       Will retrieve at most 100 new stake changes.",
+                    "Print results as a table for people to read, or as json or csv \
+                    for other programs. Amounts are given in planck, as strings in json.",
                 ),
         )
         .arg(
-            Arg::with_name("staking_rewards")
-                .long("staking_rewards")
-                .short('s')
-                .takes_value(false)
-                .help(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .value_name("name")
+                .global(true)
+                .help("Profile of the config file to use. Defaults to its default_profile."),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about(
+                    "Fetch new stake changes and staking rewards and add them to \
+                    the store selected by the profile. Nomination pool rewards are fetched too \
+                    if SUBQUERY_ENDPOINT_POOL_REWARDS is set.",
+                )
+                .arg(account_arg())
+                .arg(since_arg()),
+        )
+        .subcommand(
+            Command::new("stake-changes")
+                .alias("stake_changes")
+                .about(
+                    "Get account's stake changes. \
+                    Will skip those already in the store selected by the profile. \
+                    Fetches SUBQUERY_PAGE_SIZE (default 100) stake changes per request, \
+                    until all new ones are retrieved.",
+                )
+                .arg(account_arg())
+                .arg(since_arg()),
+        )
+        .subcommand(
+            Command::new("staking-rewards")
+                .alias("staking_rewards")
+                .about(
                     "Get account's staking rewards. \
-                       Will skip those already listed in \
-                       known_rewards file listed in .env. \
-                       Will retrieve at most 100 new rewards.",
-                ),
+                    Will skip those already in the store selected by the profile. \
+                    Fetches SUBQUERY_PAGE_SIZE (default 100) rewards per request, \
+                    until all new ones are retrieved.",
+                )
+                .arg(account_arg())
+                .arg(since_arg()),
+        )
+        .subcommand(
+            Command::new("chain-rewards")
+                .alias("chain_rewards")
+                .about(
+                    "Get account's staking and nomination pool rewards from the events of \
+                    a range of blocks, without an indexer. Every block is read, \
+                    so keep the range short, and use an archive node as RPC_ENDPOINT.",
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .value_name("block")
+                        .required(true)
+                        .help("First block to read, by number or by date"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .takes_value(true)
+                        .value_name("block")
+                        .help(
+                            "Last block to read, by number or by date. \
+                            Latest block is used if omitted.",
+                        ),
+                )
+                .arg(Arg::with_name("sync").long("sync").takes_value(false).help(
+                    "Add the rewards to the store selected by the profile \
+                            instead of printing them. Known rewards are skipped.",
+                ))
+                .arg(account_arg()),
+        )
+        .subcommand(
+            Command::new("account-balances")
+                .alias("account_balances")
+                .about("Get account's balances")
+                .arg(account_arg())
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("staking")
+                .about(
+                    "Get account's staking ledger: bonded and active funds, funds being unbonded \
+                    and when they can be withdrawn, and where rewards are paid. \
+                    The ledger is found through the stash's controller.",
+                )
+                .arg(account_arg())
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("nominations")
+                .about(
+                    "Get the validators that account nominates, with their identity name, \
+                    commission, and whether they are active in the active era. \
+                    Warns when none of them are.",
+                )
+                .arg(account_arg())
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("pools")
+                .about(
+                    "Get account's nomination pool membership: the pool, the account's points \
+                    in it, rewards pending a claim, and funds being unbonded",
+                )
+                .arg(account_arg())
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about(
+                    "Watch account's balances and print every change as it happens. \
+                    If a file is given, changes are appended to it as comma separated \
+                    values instead. Otherwise changes are also kept in the store selected \
+                    by the profile: in the sqlite store, or in KNOWN_BALANCES_FILE \
+                    if the csv store is given one. Needs a ws:// or wss:// RPC_ENDPOINT.",
+                )
+                .arg(account_arg())
+                .arg(Arg::with_name("file").index(1)),
+        )
+        .subcommand(
+            Command::new("total-issuance")
+                .alias("total_issuance")
+                .about("Get endpoint chain's total issuance")
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("era-apr")
+                .alias("era_apr")
+                .about(
+                    "Get the network's realised APR of recent eras, from what each era paid \
+                    (Staking.ErasValidatorReward) to the stake behind it (Staking.ErasTotalStake). \
+                    Commission is not taken out.",
+                )
+                .arg(
+                    Arg::with_name("eras")
+                        .long("eras")
+                        .takes_value(true)
+                        .value_name("count")
+                        .default_value("28")
+                        .help("How many of the latest ended eras to read"),
+                )
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("get-storage")
+                .alias("get_storage")
+                .about(
+                    "Raw state_getStorage rpc call. Map keys follow the storage name. \
+                    Keys are encoded and hashed as described by the runtime metadata. \
+                    Accounts can be given as addresses, \
+                    and any key can be given as 0x-prefixed hex of its encoding. \
+                    Values are decoded as described by the runtime metadata and printed as json. \
+                    Raw bytes are printed if decoding fails.",
+                )
+                .arg(Arg::with_name("module").required(true).index(1))
+                .arg(Arg::with_name("name").required(true).index(2))
+                .arg(Arg::with_name("keys").multiple_values(true).index(3))
+                .args(at_args()),
+        )
+        .subcommand(
+            Command::new("metadata")
+                .about("Call endpoint func state_getMetadata")
+                .arg(
+                    Arg::with_name("pallets")
+                        .long("pallets")
+                        .takes_value(false)
+                        .help(
+                            "List pallets with their storage items, calls, \
+                            events and constants instead of dumping all metadata",
+                        ),
+                )
+                .args(at_args()),
+        )
+        .subcommand(Command::new("properties").about("Call endpoint func system_properties"))
+        .subcommand(
+            Command::new("rpc-methods")
+                .alias("rpc_methods")
+                .about("Call endpoint func rpc_methods"),
         )
         .get_matches();
 
-    match dotenv::dotenv() {
-        Ok(_) => (),
-        Err(_) => return Err(ScError::NoEnvFile),
-    }
-
-    let rpc_endpoint = valid_rpc_endpoint_from_env()?;
-    let subquery_endpoint_rewards = valid_subquery_endpoint_rewards_from_env()?;
-    let subquery_endpoint_stake_changes = valid_subquery_endpoint_stake_changes_from_env()?;
-    let polkadot_addr = valid_polkadot_addr_from_env()?;
-    let known_rewards_file = known_rewards_file_from_env();
-    let known_stake_changes_file = known_stake_changes_file_from_env();
-    let polkadot_properties_file = polkadot_properties_file_from_env();
-
-    match Path::new(&polkadot_properties_file).try_exists() {
-        Ok(true) => (),
-        _ => {
-            eprintln!("Couldn't find {polkadot_properties_file}. Creating and populating it.");
-            let polka_props = system_properties(&rpc_endpoint).await?;
-            fs::write(&polkadot_properties_file, polka_props).expect("Unable to write file");
-        }
+    let (command, sub_matches) = matches.subcommand().expect("A subcommand is required");
+    let cx = Context {
+        command,
+        matches: sub_matches,
+        profile: load_profile(
+            sub_matches.value_of("config"),
+            sub_matches.value_of("profile"),
+        )?,
+        output: sub_matches.value_of_t("output").unwrap_or_default(),
     };
-    let token_decimals = token_decimals(polkadot_properties_file)?;
-
-    if matches.is_present("stake_changes") {
-        let stake_changes = get_stake_changes(
-            &subquery_endpoint_stake_changes,
-            &polkadot_addr,
-            &known_stake_changes_file,
-        )
-        .await?;
-        print!(
-            "{}",
-            stake_changes
-                .iter()
-                .fold(String::new(), |acc, c| acc + &c.to_string() + "\n")
-        );
-    }
-    let sr = SubqueryEndpoint::new(subquery_endpoint_rewards);
-    if matches.is_present("staking_rewards") {
-        let staking_rewards = get_staking_rewards(sr, &polkadot_addr, &known_rewards_file).await?;
-        print!(
-            "{}",
-            staking_rewards
-                .iter()
-                .fold(String::new(), |acc, r| acc + &r.to_string() + "\n")
-        );
-    }
-    if matches.is_present("rpc_methods") {
-        return rpc_methods(&rpc_endpoint).await;
-    }
-    if matches.is_present("metadata") {
-        let metadata = state_get_metadata(&rpc_endpoint).await?;
-        println!("{metadata}");
-    }
-    if matches.is_present("properties") {
-        let sys_props = system_properties(&rpc_endpoint).await?;
-        println!("{sys_props}");
-    }
-    if matches.is_present("total_issuance") {
-        let total_issuance = get_total_issuance(&rpc_endpoint).await?;
-        println!(
-            "Total issued {} DOT",
-            total_issuance.with_decimal_point(token_decimals)
-        );
-    }
-    if matches.is_present("account_balances") {
-        let account_info = get_account_info(&rpc_endpoint, &polkadot_addr).await?;
-        println!(
-            "Free: {} DOT, Reserved: {} DOT, Misc Frozen: {} DOT, Fee Frozen: {} DOT",
-            account_info.data.free.with_decimal_point(token_decimals),
-            account_info
-                .data
-                .reserved
-                .with_decimal_point(token_decimals),
-            account_info
-                .data
-                .misc_frozen
-                .with_decimal_point(token_decimals),
-            account_info
-                .data
-                .fee_frozen
-                .with_decimal_point(token_decimals)
-        );
-    }
-    if matches.is_present("get_storage") {
-        let mut known_stringifiers = HashMap::<String, Stringifier>::new();
-        known_stringifiers.insert(
-            "BalancesTotalIssuance".into(),
-            stringify_encoded_total_issuance,
-        );
-        known_stringifiers.insert("SystemAccount".into(), stringify_encoded_system_account);
-        let args: Vec<_> = matches
-            .get_many::<String>("get_storage")
-            .expect("Storage module and name are required")
-            .collect();
-
-        let key = args[0].to_owned() + args[1];
-        let bytes = match args.len() {
-            2 => state_get_storage(&rpc_endpoint, args[0], args[1], None).await?,
-            3 => state_get_storage(&rpc_endpoint, args[0], args[1], Some(args[2])).await?,
-            _ => unreachable!(),
-        };
-
-        let stringifier = known_stringifiers.get(&key);
-        match stringifier {
-            Some(stringify) => {
-                let stringified = stringify(bytes.as_slice(), token_decimals)?;
-                println!("{stringified}");
-            }
-            None => println!("{:?}", bytes),
+    match command {
+        "sync" => sync(&cx).await,
+        "stake-changes" => stake_changes(&cx).await,
+        "staking-rewards" => staking_rewards(&cx).await,
+        "chain-rewards" => chain_rewards(&cx).await,
+        "account-balances" => account_balances(&cx).await,
+        "staking" => staking(&cx).await,
+        "nominations" => nominations(&cx).await,
+        "pools" => pools(&cx).await,
+        "watch" => watch(&cx).await,
+        "total-issuance" => total_issuance(&cx).await,
+        "era-apr" => era_apr(&cx).await,
+        "get-storage" => get_storage(&cx).await,
+        "metadata" => metadata(&cx).await,
+        "properties" => {
+            json_only(&cx)?;
+            println!("{}", system_properties(&cx.rpc()?).await?);
+            Ok(())
         }
+        "rpc-methods" => {
+            json_only(&cx)?;
+            rpc_methods(&cx.rpc()?).await
+        }
+        _ => unreachable!("Unknown subcommands are rejected by clap"),
     }
-
-    Ok(())
 }
@@ -0,0 +1,371 @@
+use crate::metadata::Metadata;
+use crate::*;
+
+use crate::tests::{constant, map_entry, metadata_of, pallet, plain_entry};
+use frame_metadata::v14::{PalletMetadata, PalletStorageMetadata, StorageHasher};
+use frame_metadata::{v15, RuntimeMetadataPrefixed};
+use parity_scale_codec::Encode;
+use scale_info::{meta_type, TypeInfo};
+use serde_json::json;
+use sp_core::crypto::AccountId32;
+use sp_core::hashing;
+
+const ADDR: &str = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+
+#[derive(Encode, TypeInfo)]
+struct ActiveEraInfo {
+    index: u32,
+    start: Option<u64>,
+}
+
+#[derive(Encode, TypeInfo)]
+struct UnlockChunk {
+    #[codec(compact)]
+    value: u128,
+    #[codec(compact)]
+    era: u32,
+}
+
+#[derive(Encode, TypeInfo)]
+struct StakingLedger {
+    stash: AccountId32,
+    #[codec(compact)]
+    total: u128,
+    #[codec(compact)]
+    active: u128,
+    unlocking: Vec<UnlockChunk>,
+    claimed_rewards: Vec<u32>,
+}
+
+#[derive(Encode, TypeInfo)]
+enum RewardDestination {
+    Staked,
+    Account(AccountId32),
+}
+
+#[derive(TypeInfo)]
+#[allow(dead_code)]
+enum StakingCall {
+    Bond { value: u128 },
+    Chill,
+}
+
+#[derive(TypeInfo)]
+#[allow(dead_code)]
+enum StakingEvent {
+    Rewarded(AccountId32, u128),
+}
+
+fn test_metadata() -> Metadata {
+    use StorageHasher::{Blake2_128Concat, Twox64Concat};
+    metadata_of(vec![
+        pallet(
+            "System",
+            0,
+            vec![map_entry::<AccountId32, u128>(
+                "Account",
+                vec![Blake2_128Concat],
+            )],
+        ),
+        pallet("Balances", 5, vec![plain_entry::<u128>("TotalIssuance")]),
+        pallet(
+            "Staking",
+            7,
+            vec![
+                map_entry::<(u32, AccountId32), u128>(
+                    "ErasStakers",
+                    vec![Twox64Concat, Twox64Concat],
+                ),
+                plain_entry::<ActiveEraInfo>("ActiveEra"),
+                map_entry::<AccountId32, StakingLedger>("Ledger", vec![Blake2_128Concat]),
+                map_entry::<AccountId32, RewardDestination>("Payee", vec![Twox64Concat]),
+            ],
+        ),
+    ])
+}
+
+#[test]
+fn storage_key_matches_hard_coded_keys() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = test_metadata();
+    assert_eq!(
+        metadata.storage_key("System", "Account", &[ADDR])?,
+        "0x26aa394eea5630e07c48ae0c9558cef7b99d880ec681799c0cf30e8886371da9\
+        cacf47a79a3f57e5c7a274a3e2120cc5f5d5714c084c112843aca74f8c498da06cc5a2d63153b825189baa51043b1f0b"
+    );
+    assert_eq!(
+        metadata.storage_key("Balances", "TotalIssuance", &[])?,
+        "0xc2261276cc9d1f8598ea4b6a74b15c2f57c875e4cff74148e4628f264b974c80"
+    );
+    Ok(())
+}
+
+#[test]
+fn storage_key_of_double_map() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = test_metadata();
+    let account_id = AccountId32::from_string(ADDR)?;
+    let era = 1000u32.encode();
+    let mut expected = vec![];
+    expected.extend_from_slice(&hashing::twox_128(b"Staking"));
+    expected.extend_from_slice(&hashing::twox_128(b"ErasStakers"));
+    expected.extend_from_slice(&hashing::twox_64(&era));
+    expected.extend_from_slice(&era);
+    expected.extend_from_slice(&hashing::twox_64(account_id.as_ref()));
+    expected.extend_from_slice(account_id.as_ref());
+
+    let key = metadata.storage_key("Staking", "ErasStakers", &["1000", ADDR])?;
+    assert_eq!(key, format!("0x{}", hex::encode(&expected)));
+
+    // Keys may also be given as hex of their encoding
+    let era_hex = format!("0x{}", hex::encode(&era));
+    let key = metadata.storage_key("Staking", "ErasStakers", &[&era_hex, ADDR])?;
+    assert_eq!(key, format!("0x{}", hex::encode(&expected)));
+    Ok(())
+}
+
+#[test]
+fn storage_key_rejects_bad_input() {
+    let metadata = test_metadata();
+    assert!(matches!(
+        metadata.storage_key("Staking", "Nope", &[]),
+        Err(ScError::UnknownStorage(_))
+    ));
+    assert!(matches!(
+        metadata.storage_key("Staking", "ErasStakers", &["1000"]),
+        Err(ScError::InvalidStorageKey(_))
+    ));
+    assert!(matches!(
+        metadata.storage_key("Staking", "ErasStakers", &["era", ADDR]),
+        Err(ScError::InvalidStorageKey(_))
+    ));
+}
+
+#[test]
+fn decode_storage_values_as_json() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = test_metadata();
+    let account_id = AccountId32::from_string(ADDR)?;
+
+    let active_era = ActiveEraInfo {
+        index: 1000,
+        start: Some(1672531200000),
+    };
+    assert_eq!(
+        metadata.decode_storage_value("Staking", "ActiveEra", &active_era.encode())?,
+        json!({ "index": 1000, "start": { "Some": 1672531200000u64 } })
+    );
+
+    let ledger = StakingLedger {
+        stash: account_id.clone(),
+        total: 30_000_000_000_000_000_000,
+        active: 20_000_000_000,
+        unlocking: vec![UnlockChunk {
+            value: 10_000_000_000,
+            era: 1028,
+        }],
+        claimed_rewards: vec![998, 999],
+    };
+    assert_eq!(
+        metadata.decode_storage_value("Staking", "Ledger", &ledger.encode())?,
+        json!({
+            "stash": ADDR,
+            "total": "30000000000000000000",
+            "active": "20000000000",
+            "unlocking": [{ "value": "10000000000", "era": 1028 }],
+            "claimed_rewards": [998, 999],
+        })
+    );
+
+    assert_eq!(
+        metadata.decode_storage_value("Staking", "Payee", &RewardDestination::Staked.encode())?,
+        json!("Staked")
+    );
+    let payee = RewardDestination::Account(account_id);
+    assert_eq!(
+        metadata.decode_storage_value("Staking", "Payee", &payee.encode())?,
+        json!({ "Account": ADDR })
+    );
+
+    assert_eq!(
+        metadata.decode_storage_value("Balances", "TotalIssuance", &12u128.encode())?,
+        json!("12")
+    );
+    Ok(())
+}
+
+#[test]
+fn decode_storage_value_fails_on_short_input() {
+    let metadata = test_metadata();
+    assert!(metadata
+        .decode_storage_value("Staking", "ActiveEra", &[0xe8, 0x03])
+        .is_err());
+}
+
+#[test]
+fn decode_storage_value_fails_on_long_input() {
+    let metadata = test_metadata();
+    assert!(matches!(
+        metadata.decode_storage_value("Balances", "TotalIssuance", &[1; 17]),
+        Err(ScError::Codec(_))
+    ));
+}
+
+fn test_metadata_v15() -> Metadata {
+    let staking = v15::PalletMetadata {
+        name: "Staking",
+        storage: Some(PalletStorageMetadata {
+            prefix: "Staking",
+            entries: vec![plain_entry::<ActiveEraInfo>("ActiveEra")],
+        }),
+        calls: Some(v15::PalletCallMetadata {
+            ty: meta_type::<StakingCall>(),
+        }),
+        event: Some(v15::PalletEventMetadata {
+            ty: meta_type::<StakingEvent>(),
+        }),
+        constants: vec![v15::PalletConstantMetadata {
+            name: "BondingDuration",
+            ty: meta_type::<u32>(),
+            value: 28u32.encode(),
+            docs: vec![],
+        }],
+        error: None,
+        index: 7,
+        docs: vec![],
+    };
+    let extrinsic = v15::ExtrinsicMetadata {
+        version: 4,
+        address_ty: meta_type::<()>(),
+        call_ty: meta_type::<()>(),
+        signature_ty: meta_type::<()>(),
+        extra_ty: meta_type::<()>(),
+        signed_extensions: vec![],
+    };
+    let outer_enums = v15::OuterEnums {
+        call_enum_ty: meta_type::<()>(),
+        event_enum_ty: meta_type::<()>(),
+        error_enum_ty: meta_type::<()>(),
+    };
+    let custom = v15::CustomMetadata {
+        map: Default::default(),
+    };
+    let prefixed: RuntimeMetadataPrefixed = v15::RuntimeMetadataV15::new(
+        vec![staking],
+        extrinsic,
+        meta_type::<()>(),
+        vec![],
+        outer_enums,
+        custom,
+    )
+    .into();
+    Metadata::decode(&prefixed.encode()).unwrap()
+}
+
+#[test]
+fn v15_metadata_is_supported() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = test_metadata_v15();
+    assert_eq!(metadata.version(), 15);
+
+    let active_era = ActiveEraInfo {
+        index: 7,
+        start: None,
+    };
+    assert_eq!(
+        metadata.decode_storage_value("Staking", "ActiveEra", &active_era.encode())?,
+        json!({ "index": 7, "start": "None" })
+    );
+    Ok(())
+}
+
+#[test]
+fn pallet_summaries_list_items_by_name() {
+    assert_eq!(
+        test_metadata_v15().pallet_summaries(),
+        vec![PalletSummary {
+            name: "Staking".into(),
+            index: 7,
+            storage: vec!["ActiveEra".into()],
+            calls: vec!["Bond".into(), "Chill".into()],
+            events: vec!["Rewarded".into()],
+            constants: vec!["BondingDuration".into()],
+        }]
+    );
+
+    let v14_summaries = test_metadata().pallet_summaries();
+    assert_eq!(test_metadata().version(), 14);
+    assert_eq!(v14_summaries.len(), 3);
+    assert_eq!(
+        v14_summaries[2].storage,
+        vec!["ErasStakers", "ActiveEra", "Ledger", "Payee"]
+    );
+}
+
+#[test]
+fn decoded_accounts_use_chain_ss58_format() -> Result<(), Box<dyn std::error::Error>> {
+    let account_id = AccountId32::from_string(ADDR)?;
+    let kusama = ChainProfile {
+        ss58_format: 2,
+        token_symbol: "KSM".into(),
+        token_decimals: 12,
+    };
+    let kusama_addr = kusama.addr(&account_id);
+    let metadata = test_metadata().with_ss58_format(kusama.ss58_format);
+
+    let ledger = StakingLedger {
+        stash: account_id,
+        total: 0,
+        active: 0,
+        unlocking: vec![],
+        claimed_rewards: vec![],
+    };
+    let decoded = metadata.decode_storage_value("Staking", "Ledger", &ledger.encode())?;
+    assert_eq!(decoded["stash"], json!(kusama_addr));
+
+    // Keys are accepted as addresses of any chain
+    assert_eq!(
+        metadata.storage_key("Staking", "Ledger", &[&kusama_addr])?,
+        metadata.storage_key("Staking", "Ledger", &[ADDR])?
+    );
+    Ok(())
+}
+
+#[test]
+fn constants_decode_to_their_type() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = test_metadata_v15();
+    assert_eq!(metadata.constant::<u32>("Staking", "BondingDuration")?, 28);
+    assert!(matches!(
+        metadata.constant::<u32>("Staking", "SessionsPerEra"),
+        Err(ScError::UnknownConstant(_))
+    ));
+    assert!(matches!(
+        metadata.constant::<u64>("Staking", "BondingDuration"),
+        Err(ScError::Codec(_))
+    ));
+
+    // Polkadot's eras are 6 sessions of 2400 blocks of 6 seconds
+    let pallets = vec![
+        PalletMetadata {
+            constants: vec![
+                constant("EpochDuration", 2400u64),
+                constant("ExpectedBlockTime", 6000u64),
+            ],
+            ..pallet("Babe", 1, vec![])
+        },
+        PalletMetadata {
+            constants: vec![constant("SessionsPerEra", 6u32)],
+            ..pallet(
+                "Staking",
+                7,
+                vec![map_entry::<u32, u128>(
+                    "ErasTotalStake",
+                    vec![StorageHasher::Twox64Concat],
+                )],
+            )
+        },
+    ];
+    let metadata = metadata_of(pallets);
+    assert_eq!(era_length_millis(&metadata)?, 24 * 60 * 60 * 1000);
+    assert_eq!(
+        metadata.storage_key("Staking", "ErasTotalStake", &["1000"])?,
+        "0x5f3e4907f716ac89b6347d15ececedcaa141c4fe67c2d11f4a10c6aca7a79a04b6ff6f7d467b87a9e8030000"
+    );
+    Ok(())
+}
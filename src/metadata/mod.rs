@@ -0,0 +1,478 @@
+#[cfg(test)]
+mod metadata_tests;
+
+use std::fmt;
+
+use frame_metadata::v14::{
+    PalletStorageMetadata, StorageEntryMetadata, StorageEntryType, StorageHasher,
+};
+use frame_metadata::{RuntimeMetadata, RuntimeMetadataPrefixed};
+use parity_scale_codec::{Compact, Decode, Encode, Error as ParityScaleError};
+use scale_info::form::PortableForm;
+use scale_info::{Field, PortableRegistry, TypeDef, TypeDefPrimitive};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sp_core::crypto::{AccountId32, Ss58AddressFormat, Ss58Codec};
+use sp_core::hashing;
+
+use crate::ScError;
+
+/// The parts of the runtime metadata that we use.
+/// V14 and V15 metadata share these parts.
+pub struct Metadata {
+    version: u32,
+    types: PortableRegistry,
+    pallets: Vec<Pallet>,
+    /// Decoded accounts are shown as addresses of this SS58 format
+    ss58_format: u16,
+}
+
+struct Pallet {
+    name: String,
+    index: u8,
+    storage: Option<PalletStorageMetadata<PortableForm>>,
+    calls: Option<u32>,
+    event: Option<u32>,
+    constants: Vec<Constant>,
+}
+
+struct Constant {
+    name: String,
+    value: Vec<u8>,
+}
+
+/// Names of the storage items, calls, events and constants of one pallet.
+#[derive(Serialize, PartialEq, Eq, Debug, Clone)]
+pub struct PalletSummary {
+    pub name: String,
+    pub index: u8,
+    pub storage: Vec<String>,
+    pub calls: Vec<String>,
+    pub events: Vec<String>,
+    pub constants: Vec<String>,
+}
+
+impl fmt::Display for PalletSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} (index {})", self.name, self.index)?;
+        writeln!(f, "  Storage: {}", self.storage.join(", "))?;
+        writeln!(f, "  Calls: {}", self.calls.join(", "))?;
+        writeln!(f, "  Events: {}", self.events.join(", "))?;
+        write!(f, "  Constants: {}", self.constants.join(", "))
+    }
+}
+
+const POLKADOT_SS58_FORMAT: u16 = 0;
+
+/// V14 and V15 metadata are distinct types, with the same fields for the parts we use
+macro_rules! metadata_from {
+    ($version:literal, $metadata:ident) => {
+        Metadata {
+            version: $version,
+            types: $metadata.types,
+            pallets: $metadata
+                .pallets
+                .into_iter()
+                .map(|pallet| Pallet {
+                    name: pallet.name,
+                    index: pallet.index,
+                    storage: pallet.storage,
+                    calls: pallet.calls.map(|calls| calls.ty.id),
+                    event: pallet.event.map(|event| event.ty.id),
+                    constants: pallet
+                        .constants
+                        .into_iter()
+                        .map(|c| Constant {
+                            name: c.name,
+                            value: c.value,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            ss58_format: POLKADOT_SS58_FORMAT,
+        }
+    };
+}
+
+impl TryFrom<RuntimeMetadataPrefixed> for Metadata {
+    type Error = ScError;
+
+    fn try_from(prefixed: RuntimeMetadataPrefixed) -> Result<Self, ScError> {
+        match prefixed.1 {
+            RuntimeMetadata::V14(metadata) => Ok(metadata_from!(14, metadata)),
+            RuntimeMetadata::V15(metadata) => Ok(metadata_from!(15, metadata)),
+            other => Err(ScError::UnsupportedMetadata(other.version())),
+        }
+    }
+}
+
+impl Metadata {
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, ScError> {
+        RuntimeMetadataPrefixed::decode(&mut bytes)?.try_into()
+    }
+
+    pub fn with_ss58_format(mut self, ss58_format: u16) -> Self {
+        self.ss58_format = ss58_format;
+        self
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn pallet_summaries(&self) -> Vec<PalletSummary> {
+        self.pallets
+            .iter()
+            .map(|pallet| PalletSummary {
+                name: pallet.name.clone(),
+                index: pallet.index,
+                storage: pallet
+                    .storage
+                    .iter()
+                    .flat_map(|storage| storage.entries.iter().map(|entry| entry.name.clone()))
+                    .collect(),
+                calls: self.variant_names(pallet.calls),
+                events: self.variant_names(pallet.event),
+                constants: pallet.constants.iter().map(|c| c.name.clone()).collect(),
+            })
+            .collect()
+    }
+
+    /// Decodes the value of a pallet constant, like Staking SessionsPerEra.
+    pub fn constant<T: Decode>(
+        &self,
+        pallet_name: &str,
+        constant_name: &str,
+    ) -> Result<T, ScError> {
+        let constant = self
+            .pallets
+            .iter()
+            .find(|pallet| pallet.name == pallet_name)
+            .and_then(|pallet| pallet.constants.iter().find(|c| c.name == constant_name))
+            .ok_or_else(|| ScError::UnknownConstant(format!("{pallet_name} {constant_name}")))?;
+        Ok(T::decode(&mut constant.value.as_slice())?)
+    }
+
+    // Calls and events are each listed as one enum in the type registry
+    fn variant_names(&self, type_id: Option<u32>) -> Vec<String> {
+        match type_id
+            .and_then(|id| self.types.resolve(id))
+            .map(|t| &t.type_def)
+        {
+            Some(TypeDef::Variant(variant)) => {
+                variant.variants.iter().map(|v| v.name.clone()).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Builds the hex encoded storage key of a storage item.
+    /// Map keys are given as strings, and are encoded according to their type
+    /// before being hashed with the hashers listed in the metadata.
+    pub fn storage_key(
+        &self,
+        pallet_name: &str,
+        storage_name: &str,
+        keys: &[&str],
+    ) -> Result<String, ScError> {
+        let (storage, entry) = self.storage_entry(pallet_name, storage_name)?;
+
+        let mut storage_key = Vec::new();
+        storage_key.extend_from_slice(&hashing::twox_128(storage.prefix.as_bytes()));
+        storage_key.extend_from_slice(&hashing::twox_128(entry.name.as_bytes()));
+
+        let (hashers, key_types) = match &entry.ty {
+            StorageEntryType::Plain(_) => (vec![], vec![]),
+            StorageEntryType::Map { hashers, key, .. } => {
+                (hashers.clone(), self.key_types(key.id, hashers.len()))
+            }
+        };
+        if keys.len() != hashers.len() {
+            return Err(ScError::InvalidStorageKey(format!(
+                "{pallet_name} {storage_name} takes {} key(s), got {}",
+                hashers.len(),
+                keys.len()
+            )));
+        }
+        for ((hasher, key_type), key) in hashers.iter().zip(key_types).zip(keys) {
+            let encoded = self.encode_key(key_type, key)?;
+            storage_key.extend_from_slice(&hash(hasher, &encoded));
+        }
+
+        Ok(format!("0x{}", hex::encode(&storage_key)))
+    }
+
+    /// Decodes a storage value into json, guided by the type registry.
+    /// Bytes left over after the value mean it is not of the type the metadata tells.
+    pub fn decode_storage_value(
+        &self,
+        pallet_name: &str,
+        storage_name: &str,
+        mut bytes: &[u8],
+    ) -> Result<Value, ScError> {
+        let (_, entry) = self.storage_entry(pallet_name, storage_name)?;
+        let value_type = match &entry.ty {
+            StorageEntryType::Plain(value) => value.id,
+            StorageEntryType::Map { value, .. } => value.id,
+        };
+        let value = self.decode_value(value_type, &mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(ParityScaleError::from("Input is longer than the value").into());
+        }
+        Ok(value)
+    }
+
+    fn storage_entry(
+        &self,
+        pallet_name: &str,
+        storage_name: &str,
+    ) -> Result<
+        (
+            &PalletStorageMetadata<PortableForm>,
+            &StorageEntryMetadata<PortableForm>,
+        ),
+        ScError,
+    > {
+        let unknown = || ScError::UnknownStorage(format!("{pallet_name} {storage_name}"));
+        let storage = self
+            .pallets
+            .iter()
+            .find(|pallet| pallet.name == pallet_name)
+            .and_then(|pallet| pallet.storage.as_ref())
+            .ok_or_else(unknown)?;
+        let entry = storage
+            .entries
+            .iter()
+            .find(|entry| entry.name == storage_name)
+            .ok_or_else(unknown)?;
+        Ok((storage, entry))
+    }
+
+    /// Decodes any SCALE encoded value of a type in the registry.
+    /// Byte sequences and arrays become hex strings, accounts become addresses,
+    /// and 128 bit numbers become strings, since json numbers can't hold them.
+    pub fn decode_value(&self, type_id: u32, input: &mut &[u8]) -> Result<Value, ParityScaleError> {
+        let ty = self
+            .types
+            .resolve(type_id)
+            .ok_or("Type not found in registry")?;
+        if ty.path.ident().as_deref() == Some("AccountId32") {
+            let account_id = AccountId32::from(<[u8; 32]>::decode(input)?);
+            return Ok(json!(account_id.to_ss58check_with_version(
+                Ss58AddressFormat::custom(self.ss58_format)
+            )));
+        }
+        match &ty.type_def {
+            TypeDef::Composite(composite) => self.decode_fields(&composite.fields, input),
+            TypeDef::Variant(variant) => {
+                let index = u8::decode(input)?;
+                let variant = variant
+                    .variants
+                    .iter()
+                    .find(|v| v.index == index)
+                    .ok_or("Variant index not found")?;
+                if variant.fields.is_empty() {
+                    Ok(json!(variant.name))
+                } else {
+                    let fields = self.decode_fields(&variant.fields, input)?;
+                    Ok(json!({ &variant.name: fields }))
+                }
+            }
+            TypeDef::Sequence(sequence) => {
+                let len = Compact::<u32>::decode(input)?.0 as usize;
+                self.decode_elements(sequence.type_param.id, len, input)
+            }
+            TypeDef::Array(array) => {
+                self.decode_elements(array.type_param.id, array.len as usize, input)
+            }
+            TypeDef::Tuple(tuple) => {
+                let elements = tuple
+                    .fields
+                    .iter()
+                    .map(|field| self.decode_value(field.id, input))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(match elements.len() {
+                    0 => Value::Null,
+                    _ => Value::Array(elements),
+                })
+            }
+            TypeDef::Primitive(primitive) => decode_primitive(primitive, input),
+            TypeDef::Compact(compact) => {
+                let value = Compact::<u128>::decode(input)?.0;
+                let inner = self.types.resolve(compact.type_param.id);
+                let is_u128 = matches!(
+                    inner.map(|t| &t.type_def),
+                    Some(TypeDef::Primitive(TypeDefPrimitive::U128))
+                );
+                Ok(match u64::try_from(value) {
+                    Ok(small) if !is_u128 => json!(small),
+                    _ => json!(value.to_string()),
+                })
+            }
+            TypeDef::BitSequence(bits) => {
+                let num_bits = Compact::<u32>::decode(input)?.0 as usize;
+                let store_bytes = match self.types.resolve(bits.bit_store_type.id) {
+                    Some(store) => match &store.type_def {
+                        TypeDef::Primitive(TypeDefPrimitive::U16) => 2,
+                        TypeDef::Primitive(TypeDefPrimitive::U32) => 4,
+                        TypeDef::Primitive(TypeDefPrimitive::U64) => 8,
+                        _ => 1,
+                    },
+                    None => 1,
+                };
+                let num_stores = num_bits.div_ceil(store_bytes * 8);
+                let bytes = take_bytes(input, num_stores * store_bytes)?;
+                Ok(json!(format!("0x{}", hex::encode(bytes))))
+            }
+        }
+    }
+
+    fn decode_fields(
+        &self,
+        fields: &[Field<PortableForm>],
+        input: &mut &[u8],
+    ) -> Result<Value, ParityScaleError> {
+        if fields.iter().all(|field| field.name.is_some()) {
+            let mut object = Map::new();
+            for field in fields {
+                let name = field.name.clone().unwrap_or_default();
+                object.insert(name, self.decode_value(field.ty.id, input)?);
+            }
+            return Ok(Value::Object(object));
+        }
+        let mut elements = fields
+            .iter()
+            .map(|field| self.decode_value(field.ty.id, input))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(match elements.len() {
+            // Newtypes are shown as the type they wrap
+            1 => elements.remove(0),
+            _ => Value::Array(elements),
+        })
+    }
+
+    fn decode_elements(
+        &self,
+        element_type: u32,
+        len: usize,
+        input: &mut &[u8],
+    ) -> Result<Value, ParityScaleError> {
+        let is_byte = matches!(
+            self.types.resolve(element_type).map(|t| &t.type_def),
+            Some(TypeDef::Primitive(TypeDefPrimitive::U8))
+        );
+        if is_byte {
+            let bytes = take_bytes(input, len)?;
+            return Ok(json!(format!("0x{}", hex::encode(bytes))));
+        }
+        let elements = (0..len)
+            .map(|_| self.decode_value(element_type, input))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Array(elements))
+    }
+
+    // A map with several hashers has a tuple as key type, one element per hasher
+    fn key_types(&self, key_type: u32, num_hashers: usize) -> Vec<u32> {
+        if num_hashers > 1 {
+            if let Some(TypeDef::Tuple(tuple)) = self.types.resolve(key_type).map(|t| &t.type_def) {
+                return tuple.fields.iter().map(|field| field.id).collect();
+            }
+        }
+        vec![key_type]
+    }
+
+    fn encode_key(&self, type_id: u32, key: &str) -> Result<Vec<u8>, ScError> {
+        let invalid = || ScError::InvalidStorageKey(key.into());
+        if key.starts_with("0x") {
+            return hex::decode(key.trim_start_matches("0x")).map_err(|_| invalid());
+        }
+        let ty = self.types.resolve(type_id).ok_or_else(invalid)?;
+        match &ty.type_def {
+            TypeDef::Composite(composite) if composite.fields.len() == 1 => {
+                self.encode_key(composite.fields[0].ty.id, key)
+            }
+            TypeDef::Array(array) if array.len == 32 => {
+                let (account_id, _) =
+                    AccountId32::from_ss58check_with_version(key).map_err(|_| invalid())?;
+                Ok(<[u8; 32]>::from(account_id).to_vec())
+            }
+            TypeDef::Variant(variant) => variant
+                .variants
+                .iter()
+                .find(|v| v.name == key && v.fields.is_empty())
+                .map(|v| vec![v.index])
+                .ok_or_else(invalid),
+            TypeDef::Compact(compact) => {
+                let inner = self.encode_key(compact.type_param.id, key)?;
+                let mut padded = [0u8; 16];
+                padded[..inner.len()].copy_from_slice(&inner);
+                Ok(Compact(u128::from_le_bytes(padded)).encode())
+            }
+            TypeDef::Primitive(primitive) => encode_primitive(primitive, key).ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+fn take_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], ParityScaleError> {
+    if input.len() < len {
+        return Err("Not enough data to fill buffer".into());
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn decode_primitive(
+    primitive: &TypeDefPrimitive,
+    input: &mut &[u8],
+) -> Result<Value, ParityScaleError> {
+    Ok(match primitive {
+        TypeDefPrimitive::Bool => json!(bool::decode(input)?),
+        TypeDefPrimitive::Char => json!(char::from_u32(u32::decode(input)?)),
+        TypeDefPrimitive::Str => json!(String::decode(input)?),
+        TypeDefPrimitive::U8 => json!(u8::decode(input)?),
+        TypeDefPrimitive::U16 => json!(u16::decode(input)?),
+        TypeDefPrimitive::U32 => json!(u32::decode(input)?),
+        TypeDefPrimitive::U64 => json!(u64::decode(input)?),
+        TypeDefPrimitive::U128 => json!(u128::decode(input)?.to_string()),
+        TypeDefPrimitive::I8 => json!(i8::decode(input)?),
+        TypeDefPrimitive::I16 => json!(i16::decode(input)?),
+        TypeDefPrimitive::I32 => json!(i32::decode(input)?),
+        TypeDefPrimitive::I64 => json!(i64::decode(input)?),
+        TypeDefPrimitive::I128 => json!(i128::decode(input)?.to_string()),
+        TypeDefPrimitive::U256 | TypeDefPrimitive::I256 => {
+            json!(format!("0x{}", hex::encode(take_bytes(input, 32)?)))
+        }
+    })
+}
+
+fn encode_primitive(primitive: &TypeDefPrimitive, key: &str) -> Option<Vec<u8>> {
+    Some(match primitive {
+        TypeDefPrimitive::Bool => key.parse::<bool>().ok()?.encode(),
+        TypeDefPrimitive::Str => key.encode(),
+        TypeDefPrimitive::U8 => key.parse::<u8>().ok()?.encode(),
+        TypeDefPrimitive::U16 => key.parse::<u16>().ok()?.encode(),
+        TypeDefPrimitive::U32 => key.parse::<u32>().ok()?.encode(),
+        TypeDefPrimitive::U64 => key.parse::<u64>().ok()?.encode(),
+        TypeDefPrimitive::U128 => key.parse::<u128>().ok()?.encode(),
+        TypeDefPrimitive::I8 => key.parse::<i8>().ok()?.encode(),
+        TypeDefPrimitive::I16 => key.parse::<i16>().ok()?.encode(),
+        TypeDefPrimitive::I32 => key.parse::<i32>().ok()?.encode(),
+        TypeDefPrimitive::I64 => key.parse::<i64>().ok()?.encode(),
+        TypeDefPrimitive::I128 => key.parse::<i128>().ok()?.encode(),
+        _ => return None,
+    })
+}
+
+fn hash(hasher: &StorageHasher, data: &[u8]) -> Vec<u8> {
+    match hasher {
+        StorageHasher::Blake2_128 => hashing::blake2_128(data).to_vec(),
+        StorageHasher::Blake2_256 => hashing::blake2_256(data).to_vec(),
+        StorageHasher::Blake2_128Concat => [&hashing::blake2_128(data)[..], data].concat(),
+        StorageHasher::Twox128 => hashing::twox_128(data).to_vec(),
+        StorageHasher::Twox256 => hashing::twox_256(data).to_vec(),
+        StorageHasher::Twox64Concat => [&hashing::twox_64(data)[..], data].concat(),
+        StorageHasher::Identity => data.to_vec(),
+    }
+}
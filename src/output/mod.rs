@@ -0,0 +1,286 @@
+#[cfg(test)]
+mod output_tests;
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::{
+    ActiveEra, Balance, BondedPool, ChainProfile, NominationTarget, PolkadotAccountInfo,
+    PoolMember, ScError, StakingState,
+};
+
+/// How commands print their results.
+/// Table is meant for people, and is what commands printed before there was a choice.
+/// Json and csv keep to the schemas of the types below, for other programs to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 3] = ["table", "json", "csv"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = ScError;
+
+    fn from_str(s: &str) -> Result<Self, ScError> {
+        match s {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(ScError::UnknownOutputFormat(other.into())),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// An amount of the chain's token.
+/// Planck are kept in a string, as in [`Balance`]'s serialization.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Amount {
+    pub planck: String,
+    pub tokens: String,
+    pub symbol: String,
+}
+
+impl Amount {
+    pub fn new(planck: u128, chain: &ChainProfile) -> Self {
+        Amount {
+            planck: planck.to_string(),
+            tokens: format!("{:#}", chain.balance(planck)),
+            symbol: chain.token_symbol.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountBalances {
+    pub free: Amount,
+    pub reserved: Amount,
+    pub misc_frozen: Amount,
+    pub fee_frozen: Amount,
+}
+
+impl AccountBalances {
+    pub fn new(account_info: &PolkadotAccountInfo, chain: &ChainProfile) -> Self {
+        AccountBalances {
+            free: Amount::new(account_info.data.free.planck, chain),
+            reserved: Amount::new(account_info.data.reserved.planck, chain),
+            misc_frozen: Amount::new(account_info.data.misc_frozen.planck, chain),
+            fee_frozen: Amount::new(account_info.data.fee_frozen.planck, chain),
+        }
+    }
+}
+
+/// The System.Account storage item
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountInfo {
+    pub nonce: u32,
+    pub consumers: u32,
+    pub providers: u32,
+    pub sufficients: u32,
+    #[serde(flatten)]
+    pub balances: AccountBalances,
+}
+
+impl AccountInfo {
+    pub fn new(account_info: &PolkadotAccountInfo, chain: &ChainProfile) -> Self {
+        AccountInfo {
+            nonce: account_info.nonce,
+            consumers: account_info.consumers,
+            providers: account_info.providers,
+            sufficients: account_info.sufficients,
+            balances: AccountBalances::new(account_info, chain),
+        }
+    }
+}
+
+/// Funds being unbonded
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Unbonding {
+    pub amount: Amount,
+    /// The era from which the funds can be withdrawn
+    pub era: u32,
+    /// Eras until then, zero if the funds can be withdrawn now
+    pub eras_left: u32,
+    /// Estimated from the active era's start and the era length
+    pub withdrawable_at: Option<NaiveDateTime>,
+}
+
+/// A stash's staking ledger and reward destination
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StakingStatus {
+    pub stash: String,
+    pub controller: String,
+    pub total: Amount,
+    pub active: Amount,
+    pub unlocking: Vec<Unbonding>,
+    /// Staked, Stash, Controller, None, or the address rewards are paid to
+    pub payee: String,
+    pub active_era: u32,
+}
+
+impl StakingStatus {
+    pub fn new(
+        state: &StakingState,
+        active_era: &ActiveEra,
+        era_length_millis: u64,
+        chain: &ChainProfile,
+    ) -> Self {
+        let ledger = &state.ledger;
+        StakingStatus {
+            stash: chain.addr(&ledger.stash),
+            controller: chain.addr(&state.controller),
+            total: Amount::new(ledger.total.planck, chain),
+            active: Amount::new(ledger.active.planck, chain),
+            unlocking: ledger
+                .unlocking
+                .iter()
+                .map(|chunk| Unbonding {
+                    amount: Amount::new(chunk.value.planck, chain),
+                    era: chunk.era,
+                    eras_left: chunk.eras_left(active_era),
+                    withdrawable_at: active_era.estimated_start(chunk.era, era_length_millis),
+                })
+                .collect(),
+            payee: state.payee.describe(chain),
+            active_era: active_era.index,
+        }
+    }
+}
+
+/// A nominated validator
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Nominee {
+    pub validator: String,
+    /// The display name of the validator's identity
+    pub name: Option<String>,
+    /// None if the validator no longer intends to validate
+    pub commission_percent: Option<f64>,
+    pub blocked: bool,
+    /// Whether the validator is in the active era's active set
+    pub active: bool,
+}
+
+impl Nominee {
+    pub fn new(target: &NominationTarget, chain: &ChainProfile) -> Self {
+        Nominee {
+            validator: chain.addr(&target.validator),
+            name: target.name.clone(),
+            commission_percent: target.prefs.map(|prefs| prefs.commission_percent()),
+            blocked: target.prefs.is_some_and(|prefs| prefs.blocked),
+            active: target.active,
+        }
+    }
+}
+
+/// A stash's nominations, as they stand in the active era
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NominationsStatus {
+    pub submitted_in: u32,
+    pub active_era: u32,
+    pub nominees: Vec<Nominee>,
+}
+
+/// An account's share of a nomination pool
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PoolMembership {
+    pub pool_id: u32,
+    pub pool_name: Option<String>,
+    /// Open, Blocked or Destroying
+    pub pool_state: String,
+    pub pool_commission_percent: Option<f64>,
+    /// The account's share of the pool's bonded funds
+    pub points: Amount,
+    /// Rewards that can be claimed
+    pub pending_rewards: Amount,
+    pub unbonding: Vec<Unbonding>,
+}
+
+impl PoolMembership {
+    pub fn new(
+        member: &PoolMember,
+        pool: &BondedPool,
+        pending_rewards: Balance,
+        active_era: &ActiveEra,
+        era_length_millis: u64,
+        chain: &ChainProfile,
+    ) -> Self {
+        PoolMembership {
+            pool_id: pool.id,
+            pool_name: pool.name.clone(),
+            pool_state: pool.state.clone(),
+            pool_commission_percent: pool.commission_percent,
+            points: Amount::new(member.points.planck, chain),
+            pending_rewards: Amount::new(pending_rewards.planck, chain),
+            unbonding: member
+                .unbonding_eras
+                .iter()
+                .map(|(era, value)| Unbonding {
+                    amount: Amount::new(value.planck, chain),
+                    era: *era,
+                    eras_left: era.saturating_sub(active_era.index),
+                    withdrawable_at: active_era.estimated_start(*era, era_length_millis),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A record along with the name of the account it belongs to
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OfAccount<T> {
+    pub account: String,
+    #[serde(flatten)]
+    pub record: T,
+}
+
+impl<T> OfAccount<T> {
+    pub fn new(account: &str, record: T) -> Self {
+        OfAccount {
+            account: account.into(),
+            record,
+        }
+    }
+}
+
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), ScError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Prints a csv document with a header line
+pub fn print_csv<I, R>(header: &[&str], rows: I) -> Result<(), ScError>
+where
+    I: IntoIterator<Item = R>,
+    R: IntoIterator,
+    R::Item: AsRef<[u8]>,
+{
+    let mut wtr = csv::Writer::from_writer(std::io::stdout());
+    wtr.write_record(header)?;
+    for row in rows {
+        wtr.write_record(row)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
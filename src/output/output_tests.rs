@@ -0,0 +1,69 @@
+use super::*;
+use crate::Reward;
+use chrono::NaiveDate;
+use serde_json::json;
+
+fn dot() -> ChainProfile {
+    ChainProfile {
+        ss58_format: 0,
+        token_symbol: "DOT".into(),
+        token_decimals: 10,
+    }
+}
+
+#[test]
+fn output_formats_parse_by_name() {
+    for name in OutputFormat::NAMES {
+        assert_eq!(name.parse::<OutputFormat>().unwrap().name(), name);
+    }
+    assert!(matches!(
+        "yaml".parse::<OutputFormat>(),
+        Err(ScError::UnknownOutputFormat(_))
+    ));
+}
+
+#[test]
+fn amounts_keep_planck_precision() -> Result<(), Box<dyn std::error::Error>> {
+    let amount = Amount::new(u128::MAX, &dot());
+    assert_eq!(
+        serde_json::to_value(amount)?,
+        json!({
+            "planck": "340282366920938463463374607431768211455",
+            "tokens": "34028236692093846346337460743.1768211455",
+            "symbol": "DOT",
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn records_serialize_with_their_account() -> Result<(), Box<dyn std::error::Error>> {
+    let reward = Reward {
+        date: NaiveDate::from_ymd(2023, 1, 2).and_hms(3, 4, 5),
+        balance: 12_345_678_901.into(),
+        id: Some("0xabc-1".into()),
+    };
+    assert_eq!(
+        serde_json::to_value(OfAccount::new("stash", reward))?,
+        json!({
+            "account": "stash",
+            "date": "2023-01-02T03:04:05",
+            "balance": "12345678901",
+            "id": "0xabc-1",
+        })
+    );
+
+    let account_info = PolkadotAccountInfo {
+        nonce: 7,
+        data: pallet_balances::AccountData {
+            free: 15_000_000_000.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let json = serde_json::to_value(AccountInfo::new(&account_info, &dot()))?;
+    assert_eq!(json["nonce"], 7);
+    assert_eq!(json["free"]["tokens"], "1.5000000000");
+    assert_eq!(json["fee_frozen"]["planck"], "0");
+    Ok(())
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod pools_tests;
+
+use parity_scale_codec::Decode;
+use serde_json::Value;
+use sp_core::crypto::Ss58Codec;
+
+use crate::staking::account_id;
+use crate::{runtime_call, state_get_storage_by_key, Balance, Metadata, RpcClient, ScError};
+
+/// The NominationPools.PoolMembers storage item
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub struct PoolMember {
+    pub pool_id: u32,
+    /// The member's share of the pool's bonded funds
+    pub points: Balance,
+    pub last_recorded_reward_counter: u128,
+    /// Funds being unbonded, by the era they can be withdrawn from
+    pub unbonding_eras: Vec<(u32, Balance)>,
+}
+
+/// A pool as told by NominationPools.BondedPools and NominationPools.Metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct BondedPool {
+    pub id: u32,
+    /// The name the pool's operator gave it
+    pub name: Option<String>,
+    /// Open, Blocked or Destroying
+    pub state: String,
+    pub member_counter: u32,
+    pub points: Balance,
+    pub commission_percent: Option<f64>,
+}
+
+impl BondedPool {
+    /// Fields have been added to and moved about in BondedPools over runtime upgrades,
+    /// so the pool is read from its value as decoded by the runtime metadata.
+    fn from_decoded(id: u32, name: Option<String>, decoded: &Value) -> Result<Self, ScError> {
+        let invalid = |field: &str| {
+            ScError::InvalidStorageValue(format!("NominationPools.BondedPools {id} {field}"))
+        };
+        let points: u128 = match &decoded["points"] {
+            Value::String(points) => points.parse().ok(),
+            points => points.as_u64().map(u128::from),
+        }
+        .ok_or_else(|| invalid("points"))?;
+        let member_counter = decoded["member_counter"]
+            .as_u64()
+            .and_then(|counter| u32::try_from(counter).ok())
+            .ok_or_else(|| invalid("member_counter"))?;
+        let state = decoded["state"].as_str().ok_or_else(|| invalid("state"))?;
+        Ok(BondedPool {
+            id,
+            name,
+            state: state.into(),
+            member_counter,
+            points: points.into(),
+            // Commission is a Perbill, along with the account it is paid to
+            commission_percent: decoded["commission"]["current"]["Some"][0]
+                .as_u64()
+                .map(|perbill| perbill as f64 / 10_000_000.),
+        })
+    }
+}
+
+/// Gives NotPoolMember if the account is in no pool
+pub async fn get_pool_member(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    polkadot_addr: &str,
+    at: Option<&str>,
+) -> Result<PoolMember, ScError> {
+    let member = account_id(polkadot_addr)?;
+    let key = metadata.storage_key("NominationPools", "PoolMembers", &[&member.to_ss58check()])?;
+    match state_get_storage_by_key(rpc, key, at).await {
+        Ok(result_bytes) => Ok(PoolMember::decode(&mut result_bytes.as_slice())?),
+        Err(ScError::NoDataFound) => Err(ScError::NotPoolMember(polkadot_addr.into())),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn get_bonded_pool(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    pool_id: u32,
+    at: Option<&str>,
+) -> Result<BondedPool, ScError> {
+    let pool_key = pool_id.to_string();
+    let key = metadata.storage_key("NominationPools", "BondedPools", &[&pool_key])?;
+    let result_bytes = state_get_storage_by_key(rpc, key, at).await?;
+    let decoded = metadata.decode_storage_value("NominationPools", "BondedPools", &result_bytes)?;
+    let name_key = metadata.storage_key("NominationPools", "Metadata", &[&pool_key])?;
+    let name = match state_get_storage_by_key(rpc, name_key, at).await {
+        Ok(name_bytes) => {
+            let name = Vec::<u8>::decode(&mut name_bytes.as_slice())?;
+            Some(String::from_utf8_lossy(&name).into_owned()).filter(|name| !name.is_empty())
+        }
+        Err(ScError::NoDataFound) => None,
+        Err(err) => return Err(err),
+    };
+    BondedPool::from_decoded(pool_id, name, &decoded)
+}
+
+/// Rewards the member can claim from its pool, from the NominationPoolsApi runtime API
+pub async fn get_pending_pool_rewards(
+    rpc: &RpcClient,
+    polkadot_addr: &str,
+    at: Option<&str>,
+) -> Result<Balance, ScError> {
+    let member = account_id(polkadot_addr)?;
+    runtime_call(rpc, "NominationPoolsApi_pending_rewards", &member, at).await
+}
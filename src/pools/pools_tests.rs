@@ -0,0 +1,110 @@
+use super::*;
+use crate::tests::{map_entry, metadata_of, mock_rpc_result, pallet};
+use frame_metadata::v14::StorageHasher;
+use parity_scale_codec::Encode;
+use serde_json::json;
+use sp_core::crypto::{AccountId32, Ss58Codec};
+
+const MEMBER: &str = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+
+/// NominationPools.PoolMembers with the hasher of Polkadot's runtime
+fn test_metadata() -> Metadata {
+    metadata_of(vec![pallet(
+        "NominationPools",
+        39,
+        vec![map_entry::<AccountId32, u32>(
+            "PoolMembers",
+            vec![StorageHasher::Twox64Concat],
+        )],
+    )])
+}
+
+#[tokio::test]
+async fn pool_member_and_pending_rewards() -> Result<(), Box<dyn std::error::Error>> {
+    let member = AccountId32::from_string(MEMBER)?;
+    let encoded_member = (
+        12u32,
+        20_000_000_000u128,
+        7u128,
+        vec![(1028u32, 5_000_000_000u128)],
+    );
+    let metadata = test_metadata();
+    let member_mock = mock_rpc_result(
+        "state_getStorage",
+        json!([metadata.storage_key("NominationPools", "PoolMembers", &[MEMBER])?]),
+        json!(format!("0x{}", hex::encode(encoded_member.encode()))),
+    );
+    let pending_mock = mock_rpc_result(
+        "state_call",
+        json!([
+            "NominationPoolsApi_pending_rewards",
+            format!("0x{}", hex::encode(member.encode()))
+        ]),
+        json!(format!("0x{}", hex::encode(1_234_567u128.encode()))),
+    );
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let pool_member = get_pool_member(&rpc, &metadata, MEMBER, None).await?;
+    let pending = get_pending_pool_rewards(&rpc, MEMBER, None).await?;
+
+    member_mock.assert();
+    pending_mock.assert();
+    assert_eq!(
+        pool_member,
+        PoolMember {
+            pool_id: 12,
+            points: Balance::from(20_000_000_000),
+            last_recorded_reward_counter: 7,
+            unbonding_eras: vec![(1028, Balance::from(5_000_000_000))],
+        }
+    );
+    assert_eq!(pending, Balance::from(1_234_567));
+    Ok(())
+}
+
+#[test]
+fn bonded_pools_are_read_by_field_name() -> Result<(), ScError> {
+    let decoded = json!({
+        "commission": {
+            "current": { "Some": [50_000_000, MEMBER] },
+            "max": "None",
+        },
+        "member_counter": 42,
+        "points": "340282366920938463463374607431768211455",
+        "roles": {},
+        "state": "Open",
+    });
+    let pool = BondedPool::from_decoded(12, Some("Pool".into()), &decoded)?;
+    assert_eq!(
+        pool,
+        BondedPool {
+            id: 12,
+            name: Some("Pool".into()),
+            state: "Open".into(),
+            member_counter: 42,
+            points: Balance::from(u128::MAX),
+            commission_percent: Some(5.),
+        }
+    );
+    // Pools from before commissions were added have none
+    let decoded = json!({ "points": 1, "state": "Blocked", "member_counter": 1 });
+    assert_eq!(
+        BondedPool::from_decoded(1, None, &decoded)?.commission_percent,
+        None
+    );
+    Ok(())
+}
+
+#[test]
+fn bonded_pools_missing_a_field_are_an_error() {
+    let decoded = json!({ "points": 1, "state": "Open" });
+    assert!(matches!(
+        BondedPool::from_decoded(1, None, &decoded),
+        Err(ScError::InvalidStorageValue(field)) if field.ends_with("member_counter")
+    ));
+    let decoded = json!({ "points": "lots", "state": "Open", "member_counter": 1 });
+    assert!(matches!(
+        BondedPool::from_decoded(1, None, &decoded),
+        Err(ScError::InvalidStorageValue(field)) if field.ends_with("points")
+    ));
+}
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod runtime_api_tests;
+
+use parity_scale_codec::{Decode, DecodeAll, Encode, Output};
+
+use crate::{state_call, Balance, RpcClient, ScError};
+
+/// Calls a runtime API function with SCALE encoded arguments,
+/// and decodes its result as `R`, which must take all of the result's bytes.
+/// Several arguments are given as a tuple, in the order the function takes them.
+pub async fn runtime_call<A: Encode, R: Decode>(
+    rpc: &RpcClient,
+    function: &str,
+    args: &A,
+    at: Option<&str>,
+) -> Result<R, ScError> {
+    let result_bytes = state_call(rpc, function, &args.encode(), at).await?;
+    Ok(R::decode_all(&mut result_bytes.as_slice())?)
+}
+
+/// The most validators a nominator with this much bonded may nominate
+pub async fn get_nominations_quota(
+    rpc: &RpcClient,
+    bonded: Balance,
+    at: Option<&str>,
+) -> Result<u32, ScError> {
+    runtime_call(rpc, "StakingApi_nominations_quota", &bonded, at).await
+}
+
+/// An extrinsic as it is submitted to the chain, already SCALE encoded
+struct EncodedExtrinsic<'a>(&'a [u8]);
+
+impl Encode for EncodedExtrinsic<'_> {
+    fn size_hint(&self) -> usize {
+        self.0.len()
+    }
+
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        dest.write(self.0)
+    }
+}
+
+/// The weight of a call, in its two dimensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode)]
+pub struct Weight {
+    /// Picoseconds of computation
+    #[codec(compact)]
+    pub ref_time: u64,
+    /// Bytes of proof for light clients
+    #[codec(compact)]
+    pub proof_size: u64,
+}
+
+/// Which part of a block's weight a call may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode)]
+pub enum DispatchClass {
+    Normal,
+    Operational,
+    Mandatory,
+}
+
+/// What TransactionPaymentApi_query_info tells of an extrinsic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Decode)]
+pub struct RuntimeDispatchInfo {
+    pub weight: Weight,
+    pub class: DispatchClass,
+    /// The fee of the extrinsic, without its tip
+    pub partial_fee: Balance,
+}
+
+/// Weight and fee of a signed extrinsic, given as the bytes submitted to the chain
+pub async fn query_fee_info(
+    rpc: &RpcClient,
+    extrinsic: &[u8],
+    at: Option<&str>,
+) -> Result<RuntimeDispatchInfo, ScError> {
+    let args = (EncodedExtrinsic(extrinsic), extrinsic.len() as u32);
+    runtime_call(rpc, "TransactionPaymentApi_query_info", &args, at).await
+}
@@ -0,0 +1,97 @@
+use super::*;
+use crate::tests::mock_rpc_result;
+use parity_scale_codec::Compact;
+use serde_json::json;
+
+fn hex_of(encoded: impl Encode) -> String {
+    format!("0x{}", hex::encode(encoded.encode()))
+}
+
+#[tokio::test]
+async fn nominations_quota_happy_case() -> Result<(), Box<dyn std::error::Error>> {
+    let bonded = Balance::from(500_000_000_000);
+    let mock = mock_rpc_result(
+        "state_call",
+        json!(["StakingApi_nominations_quota", hex_of(bonded)]),
+        json!(hex_of(16u32)),
+    );
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let quota = get_nominations_quota(&rpc, bonded, None).await?;
+
+    mock.assert();
+    assert_eq!(quota, 16);
+    Ok(())
+}
+
+#[tokio::test]
+async fn fee_info_is_asked_for_the_extrinsic_and_its_length(
+) -> Result<(), Box<dyn std::error::Error>> {
+    // An extrinsic comes with its length prefix, which is not encoded again
+    let extrinsic = [Compact(3u32).encode(), vec![0x84, 0x05, 0x00]].concat();
+    let args = [extrinsic.clone(), 4u32.encode()].concat();
+    let block_hash = "0x91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3";
+    let mock = mock_rpc_result(
+        "state_call",
+        json!([
+            "TransactionPaymentApi_query_info",
+            format!("0x{}", hex::encode(args)),
+            block_hash
+        ]),
+        json!(hex_of((
+            Compact(190_000_000u64),
+            Compact(3_593u64),
+            0u8,
+            155_000_000u128
+        ))),
+    );
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let info = query_fee_info(&rpc, &extrinsic, Some(block_hash)).await?;
+
+    mock.assert();
+    assert_eq!(
+        info,
+        RuntimeDispatchInfo {
+            weight: Weight {
+                ref_time: 190_000_000,
+                proof_size: 3_593,
+            },
+            class: DispatchClass::Normal,
+            partial_fee: Balance::from(155_000_000),
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn runtime_call_fails_on_results_of_another_type() {
+    let mock = mock_rpc_result(
+        "state_call",
+        json!(["StakingApi_nominations_quota", hex_of(Balance::from(1))]),
+        // A single byte is not a u32
+        json!("0x10"),
+    );
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let quota = get_nominations_quota(&rpc, Balance::from(1), None).await;
+
+    mock.assert();
+    assert!(matches!(quota, Err(ScError::Codec(_))));
+}
+
+#[tokio::test]
+async fn runtime_call_fails_on_results_longer_than_their_type() {
+    let mock = mock_rpc_result(
+        "state_call",
+        json!(["StakingApi_nominations_quota", hex_of(Balance::from(2))]),
+        // A u64 starts with the bytes of a u32 of the same value
+        json!(hex_of(16u64)),
+    );
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let quota = get_nominations_quota(&rpc, Balance::from(2), None).await;
+
+    mock.assert();
+    assert!(matches!(quota, Err(ScError::Codec(_))));
+}
@@ -0,0 +1,485 @@
+#[cfg(test)]
+mod staking_tests;
+
+use chrono::{Duration, NaiveDateTime};
+use parity_scale_codec::{Compact, Decode, Input};
+use serde::Serialize;
+use sp_core::crypto::{AccountId32, Ss58Codec};
+
+use crate::{
+    state_get_storage, state_get_storage_by_key, Balance, ChainProfile, Metadata, RpcClient,
+    ScError,
+};
+
+const MILLIS_PER_YEAR: f64 = 365. * 24. * 60. * 60. * 1000.;
+
+/// The era that validators are currently rewarded for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveEra {
+    pub index: u32,
+    /// Set by the first block of the era
+    pub start: Option<NaiveDateTime>,
+}
+
+impl ActiveEra {
+    /// When an earlier or later era started or will start, if eras keep their length
+    pub fn estimated_start(&self, era: u32, era_length_millis: u64) -> Option<NaiveDateTime> {
+        let eras_after = era as i64 - self.index as i64;
+        self.start?.checked_add_signed(Duration::milliseconds(
+            eras_after * era_length_millis as i64,
+        ))
+    }
+}
+
+/// What staking in an era paid, as a yearly rate.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EraApr {
+    pub era: u32,
+    /// Estimated from the active era's start and the era length
+    pub start: Option<NaiveDateTime>,
+    /// Paid to validators and their nominators together
+    pub validator_reward: Balance,
+    /// Stake backing the active validators
+    pub total_stake: Balance,
+    pub apr: f64,
+}
+
+impl EraApr {
+    pub fn new(
+        era: u32,
+        validator_reward: Balance,
+        total_stake: Balance,
+        era_length_millis: u64,
+    ) -> Self {
+        EraApr {
+            era,
+            start: None,
+            validator_reward,
+            total_stake,
+            apr: realised_apr(validator_reward, total_stake, era_length_millis),
+        }
+    }
+}
+
+/// The era's reward per staked token, repeated for a year's worth of eras.
+/// Rewards are not compounded, like the APR shown by staking dashboards.
+pub fn realised_apr(
+    validator_reward: Balance,
+    total_stake: Balance,
+    era_length_millis: u64,
+) -> f64 {
+    if total_stake.planck == 0 || era_length_millis == 0 {
+        return 0.;
+    }
+    let eras_per_year = MILLIS_PER_YEAR / era_length_millis as f64;
+    validator_reward.planck as f64 / total_stake.planck as f64 * eras_per_year
+}
+
+/// An era lasts Staking.SessionsPerEra sessions of Babe.EpochDuration blocks,
+/// produced every Babe.ExpectedBlockTime milliseconds.
+pub fn era_length_millis(metadata: &Metadata) -> Result<u64, ScError> {
+    let sessions_per_era: u32 = metadata.constant("Staking", "SessionsPerEra")?;
+    let epoch_duration: u64 = metadata.constant("Babe", "EpochDuration")?;
+    let block_time_millis: u64 = metadata.constant("Babe", "ExpectedBlockTime")?;
+    Ok(sessions_per_era as u64 * epoch_duration * block_time_millis)
+}
+
+pub async fn get_active_era(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    at: Option<&str>,
+) -> Result<ActiveEra, ScError> {
+    let result_bytes = state_get_storage(rpc, metadata, "Staking", "ActiveEra", &[], at).await?;
+    let (index, start) = <(u32, Option<u64>)>::decode(&mut result_bytes.as_slice())?;
+    Ok(ActiveEra {
+        index,
+        start: start.and_then(|millis| {
+            NaiveDateTime::from_timestamp_opt(
+                (millis / 1000) as i64,
+                (millis % 1000) as u32 * 1_000_000,
+            )
+        }),
+    })
+}
+
+/// Reads what an era paid, and the stake it was paid to.
+/// Only eras that have ended have a reward, and the chain prunes eras
+/// older than its history depth. Both give NoDataFound.
+pub async fn get_era_apr(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    era: u32,
+    at: Option<&str>,
+) -> Result<EraApr, ScError> {
+    let era_key = era.to_string();
+    let reward_key = metadata.storage_key("Staking", "ErasValidatorReward", &[&era_key])?;
+    let reward_bytes = state_get_storage_by_key(rpc, reward_key, at).await?;
+    let stake_key = metadata.storage_key("Staking", "ErasTotalStake", &[&era_key])?;
+    let stake_bytes = state_get_storage_by_key(rpc, stake_key, at).await?;
+    Ok(EraApr::new(
+        era,
+        Balance::decode(&mut reward_bytes.as_slice())?,
+        Balance::decode(&mut stake_bytes.as_slice())?,
+        era_length_millis(metadata)?,
+    ))
+}
+
+/// The realised APR of up to `eras` eras that ended before the active era, oldest first.
+/// Eras pruned by the chain are left out.
+pub async fn get_recent_era_aprs(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    eras: u32,
+    at: Option<&str>,
+) -> Result<Vec<EraApr>, ScError> {
+    let era_length = era_length_millis(metadata)?;
+    let active_era = get_active_era(rpc, metadata, at).await?;
+    let mut aprs = vec![];
+    for era in active_era.index.saturating_sub(eras)..active_era.index {
+        match get_era_apr(rpc, metadata, era, at).await {
+            Ok(era_apr) => aprs.push(EraApr {
+                start: active_era.estimated_start(era, era_length),
+                ..era_apr
+            }),
+            Err(ScError::NoDataFound) => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(aprs)
+}
+
+/// The mean APR of eras, or None if there are none
+pub fn mean_apr(aprs: &[EraApr]) -> Option<f64> {
+    match aprs.len() {
+        0 => None,
+        n => Some(aprs.iter().map(|a| a.apr).sum::<f64>() / n as f64),
+    }
+}
+
+/// Bonded funds that become free to withdraw at the start of an era
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnlockChunk {
+    pub value: Balance,
+    pub era: u32,
+}
+
+impl UnlockChunk {
+    /// Eras until the funds can be withdrawn, zero if they already can
+    pub fn eras_left(&self, active_era: &ActiveEra) -> u32 {
+        self.era.saturating_sub(active_era.index)
+    }
+}
+
+/// The Staking.Ledger storage item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StakingLedger {
+    pub stash: AccountId32,
+    /// Active and unlocking funds together
+    pub total: Balance,
+    /// Funds that are staked in the coming eras
+    pub active: Balance,
+    pub unlocking: Vec<UnlockChunk>,
+}
+
+/// Runtimes have added and renamed fields after unlocking, so those are not decoded
+impl Decode for StakingLedger {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+        let stash = AccountId32::decode(input)?;
+        let total = Compact::<u128>::decode(input)?.0;
+        let active = Compact::<u128>::decode(input)?.0;
+        let unlocking = Vec::<(Compact<u128>, Compact<u32>)>::decode(input)?;
+        Ok(StakingLedger {
+            stash,
+            total: total.into(),
+            active: active.into(),
+            unlocking: unlocking
+                .into_iter()
+                .map(|(value, era)| UnlockChunk {
+                    value: value.0.into(),
+                    era: era.0,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Where staking rewards are paid, the Staking.Payee storage item
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub enum RewardDestination {
+    /// Paid to the stash and bonded
+    Staked,
+    Stash,
+    Controller,
+    Account(AccountId32),
+    None,
+}
+
+impl RewardDestination {
+    /// Names the destination, with accounts given as addresses of the chain
+    pub fn describe(&self, chain: &ChainProfile) -> String {
+        match self {
+            RewardDestination::Staked => "Staked".into(),
+            RewardDestination::Stash => "Stash".into(),
+            RewardDestination::Controller => "Controller".into(),
+            RewardDestination::Account(account_id) => chain.addr(account_id),
+            RewardDestination::None => "None".into(),
+        }
+    }
+}
+
+pub(crate) fn account_id(polkadot_addr: &str) -> Result<AccountId32, ScError> {
+    AccountId32::from_string(polkadot_addr)
+        .map_err(|_| ScError::InvalidPolkadotAddr(polkadot_addr.into()))
+}
+
+/// The controller account of a stash, from Staking.Bonded.
+/// Gives NotBonded if the stash doesn't stake.
+pub async fn get_controller(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    stash_addr: &str,
+    at: Option<&str>,
+) -> Result<AccountId32, ScError> {
+    let stash = account_id(stash_addr)?;
+    let key = metadata.storage_key("Staking", "Bonded", &[&stash.to_ss58check()])?;
+    match state_get_storage_by_key(rpc, key, at).await {
+        Ok(result_bytes) => Ok(AccountId32::decode(&mut result_bytes.as_slice())?),
+        Err(ScError::NoDataFound) => Err(ScError::NotBonded(stash_addr.into())),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn get_staking_ledger(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    controller: &AccountId32,
+    at: Option<&str>,
+) -> Result<StakingLedger, ScError> {
+    let key = metadata.storage_key("Staking", "Ledger", &[&controller.to_ss58check()])?;
+    let result_bytes = state_get_storage_by_key(rpc, key, at).await?;
+    Ok(StakingLedger::decode(&mut result_bytes.as_slice())?)
+}
+
+pub async fn get_payee(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    stash_addr: &str,
+    at: Option<&str>,
+) -> Result<RewardDestination, ScError> {
+    let stash = account_id(stash_addr)?;
+    let key = metadata.storage_key("Staking", "Payee", &[&stash.to_ss58check()])?;
+    match state_get_storage_by_key(rpc, key, at).await {
+        Ok(result_bytes) => Ok(RewardDestination::decode(&mut result_bytes.as_slice())?),
+        // Staked is what the chain assumes when no destination is stored
+        Err(ScError::NoDataFound) => Ok(RewardDestination::Staked),
+        Err(err) => Err(err),
+    }
+}
+
+/// A stash's ledger and reward destination, as resolved through its controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StakingState {
+    pub controller: AccountId32,
+    pub ledger: StakingLedger,
+    pub payee: RewardDestination,
+}
+
+pub async fn get_staking_state(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    stash_addr: &str,
+    at: Option<&str>,
+) -> Result<StakingState, ScError> {
+    let controller = get_controller(rpc, metadata, stash_addr, at).await?;
+    let ledger = get_staking_ledger(rpc, metadata, &controller, at).await?;
+    let payee = get_payee(rpc, metadata, stash_addr, at).await?;
+    Ok(StakingState {
+        controller,
+        ledger,
+        payee,
+    })
+}
+
+/// The Staking.Nominators storage item
+#[derive(Debug, Clone, PartialEq, Eq, Decode)]
+pub struct Nominations {
+    pub targets: Vec<AccountId32>,
+    /// The era the nominations were made in
+    pub submitted_in: u32,
+    pub suppressed: bool,
+}
+
+/// The Staking.Validators storage item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatorPrefs {
+    /// Parts per billion of the rewards that the validator keeps
+    pub commission: u32,
+    /// Whether the validator refuses new nominations
+    pub blocked: bool,
+}
+
+impl ValidatorPrefs {
+    pub fn commission_percent(&self) -> f64 {
+        self.commission as f64 / 10_000_000.
+    }
+}
+
+impl Decode for ValidatorPrefs {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, parity_scale_codec::Error> {
+        Ok(ValidatorPrefs {
+            commission: Compact::<u32>::decode(input)?.0,
+            blocked: bool::decode(input)?,
+        })
+    }
+}
+
+/// A nominated validator as it stands in an era
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NominationTarget {
+    pub validator: AccountId32,
+    /// The display name of the validator's on-chain identity
+    pub name: Option<String>,
+    /// None if the validator no longer intends to validate
+    pub prefs: Option<ValidatorPrefs>,
+    /// Whether the validator is in the era's active set
+    pub active: bool,
+}
+
+/// Gives NotNominating if the stash doesn't nominate
+pub async fn get_nominations(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    stash_addr: &str,
+    at: Option<&str>,
+) -> Result<Nominations, ScError> {
+    let stash = account_id(stash_addr)?;
+    let key = metadata.storage_key("Staking", "Nominators", &[&stash.to_ss58check()])?;
+    match state_get_storage_by_key(rpc, key, at).await {
+        Ok(result_bytes) => Ok(Nominations::decode(&mut result_bytes.as_slice())?),
+        Err(ScError::NoDataFound) => Err(ScError::NotNominating(stash_addr.into())),
+        Err(err) => Err(err),
+    }
+}
+
+/// None if the account is not a validator, or has chilled
+pub async fn get_validator_prefs(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    validator: &AccountId32,
+    at: Option<&str>,
+) -> Result<Option<ValidatorPrefs>, ScError> {
+    let key = metadata.storage_key("Staking", "Validators", &[&validator.to_ss58check()])?;
+    match state_get_storage_by_key(rpc, key, at).await {
+        Ok(result_bytes) => Ok(Some(ValidatorPrefs::decode(&mut result_bytes.as_slice())?)),
+        Err(ScError::NoDataFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether the validator has an exposure in the era.
+/// Runtimes with paged exposures keep them in ErasStakersOverview, older ones in ErasStakers.
+pub async fn is_active_validator(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    era: u32,
+    validator: &AccountId32,
+    at: Option<&str>,
+) -> Result<bool, ScError> {
+    let keys = [era.to_string(), validator.to_ss58check()];
+    for storage_name in ["ErasStakersOverview", "ErasStakers"] {
+        let key = match metadata.storage_key("Staking", storage_name, &[&keys[0], &keys[1]]) {
+            Ok(key) => key,
+            Err(ScError::UnknownStorage(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        match state_get_storage_by_key(rpc, key, at).await {
+            // An empty exposure is what ErasStakers gives validators outside the active set
+            Ok(result_bytes) => {
+                return Ok(Compact::<u128>::decode(&mut result_bytes.as_slice())?.0 > 0)
+            }
+            Err(ScError::NoDataFound) => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(false)
+}
+
+/// Decodes the Data type of the Identity pallet.
+/// Only raw data, which is how names are kept, is shown.
+fn decode_identity_data<I: Input>(
+    input: &mut I,
+) -> Result<Option<String>, parity_scale_codec::Error> {
+    match input.read_byte()? {
+        0 => Ok(None),
+        // Raw data of 0 to 32 bytes
+        variant @ 1..=33 => {
+            let mut raw = vec![0; variant as usize - 1];
+            input.read(&mut raw)?;
+            Ok(Some(String::from_utf8_lossy(&raw).into_owned()))
+        }
+        // Hashes of data
+        34..=37 => {
+            <[u8; 32]>::decode(input)?;
+            Ok(None)
+        }
+        _ => Err("Unknown identity data".into()),
+    }
+}
+
+/// The display name of an account's identity, from Identity.IdentityOf.
+/// None if the account has no identity, or the chain no identity pallet.
+pub async fn get_identity_name(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    account_id: &AccountId32,
+    at: Option<&str>,
+) -> Result<Option<String>, ScError> {
+    let key = match metadata.storage_key("Identity", "IdentityOf", &[&account_id.to_ss58check()]) {
+        Ok(key) => key,
+        Err(ScError::UnknownStorage(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let result_bytes = match state_get_storage_by_key(rpc, key, at).await {
+        Ok(result_bytes) => result_bytes,
+        Err(ScError::NoDataFound) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    // A registration starts with judgements and a deposit,
+    // and its info with additional fields before the display name
+    let input = &mut result_bytes.as_slice();
+    let judgements = Compact::<u32>::decode(input)?.0;
+    for _ in 0..judgements {
+        u32::decode(input)?;
+        if input.read_byte()? == 1 {
+            // A judgement of FeePaid holds the fee
+            u128::decode(input)?;
+        }
+    }
+    u128::decode(input)?;
+    let additional = Compact::<u32>::decode(input)?.0;
+    for _ in 0..additional * 2 {
+        decode_identity_data(input)?;
+    }
+    Ok(decode_identity_data(input)?)
+}
+
+/// Looks up each nominated validator's commission, name,
+/// and whether it is active in the active era
+pub async fn get_nomination_targets(
+    rpc: &RpcClient,
+    metadata: &Metadata,
+    nominations: &Nominations,
+    active_era: &ActiveEra,
+    at: Option<&str>,
+) -> Result<Vec<NominationTarget>, ScError> {
+    let mut targets = vec![];
+    for validator in &nominations.targets {
+        targets.push(NominationTarget {
+            validator: validator.clone(),
+            name: get_identity_name(rpc, metadata, validator, at).await?,
+            prefs: get_validator_prefs(rpc, metadata, validator, at).await?,
+            active: is_active_validator(rpc, metadata, active_era.index, validator, at).await?,
+        });
+    }
+    Ok(targets)
+}
@@ -0,0 +1,346 @@
+use super::*;
+use crate::tests::{constant, map_entry, metadata_of, mock_rpc_result, pallet, plain_entry};
+use chrono::NaiveDate;
+use frame_metadata::v14::{PalletMetadata, StorageHasher};
+use parity_scale_codec::Encode;
+use serde_json::json;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Storage items with the hashers of Polkadot's runtime, and eras of a day
+fn test_metadata() -> Metadata {
+    use StorageHasher::{Blake2_128Concat, Twox64Concat};
+    let pallets = vec![
+        PalletMetadata {
+            constants: vec![
+                constant("EpochDuration", 2400u64),
+                constant("ExpectedBlockTime", 6000u64),
+            ],
+            ..pallet("Babe", 1, vec![])
+        },
+        PalletMetadata {
+            constants: vec![constant("SessionsPerEra", 6u32)],
+            ..pallet(
+                "Staking",
+                7,
+                vec![
+                    plain_entry::<(u32, Option<u64>)>("ActiveEra"),
+                    map_entry::<u32, u128>("ErasValidatorReward", vec![Twox64Concat]),
+                    map_entry::<u32, u128>("ErasTotalStake", vec![Twox64Concat]),
+                    map_entry::<AccountId32, AccountId32>("Bonded", vec![Twox64Concat]),
+                    map_entry::<AccountId32, u128>("Ledger", vec![Blake2_128Concat]),
+                    map_entry::<AccountId32, u8>("Payee", vec![Twox64Concat]),
+                    map_entry::<AccountId32, u8>("Nominators", vec![Twox64Concat]),
+                    map_entry::<AccountId32, u8>("Validators", vec![Twox64Concat]),
+                    map_entry::<(u32, AccountId32), u8>(
+                        "ErasStakersOverview",
+                        vec![Twox64Concat, Twox64Concat],
+                    ),
+                    map_entry::<(u32, AccountId32), u8>(
+                        "ErasStakers",
+                        vec![Twox64Concat, Twox64Concat],
+                    ),
+                ],
+            )
+        },
+        pallet(
+            "Identity",
+            28,
+            vec![map_entry::<AccountId32, u8>(
+                "IdentityOf",
+                vec![Twox64Concat],
+            )],
+        ),
+    ];
+    metadata_of(pallets)
+}
+
+#[test]
+fn realised_apr_repeats_era_return_for_a_year() {
+    // Paying 1 in 3650 every day is 10% a year
+    let apr = realised_apr(Balance::from(100), Balance::from(365_000), DAY_MILLIS);
+    assert!((apr - 0.1).abs() < 1e-12);
+    // Eras of half a day pay twice as often
+    let apr = realised_apr(Balance::from(100), Balance::from(365_000), DAY_MILLIS / 2);
+    assert!((apr - 0.2).abs() < 1e-12);
+    assert_eq!(
+        realised_apr(Balance::from(100), Balance::from(0), DAY_MILLIS),
+        0.
+    );
+}
+
+#[tokio::test]
+async fn recent_era_aprs_skip_pruned_eras() -> Result<(), Box<dyn std::error::Error>> {
+    let active_start = NaiveDate::from_ymd(2023, 1, 10).and_hms(0, 0, 0);
+    let active_era = (1000u32, Some(active_start.timestamp_millis() as u64));
+    let metadata = test_metadata();
+    let era_key = |storage_name, era: u32| {
+        metadata
+            .storage_key("Staking", storage_name, &[&era.to_string()])
+            .unwrap()
+    };
+    let mut mocks = vec![mock_rpc_result(
+        "state_getStorage",
+        json!([metadata.storage_key("Staking", "ActiveEra", &[])?]),
+        json!(format!("0x{}", hex::encode(active_era.encode()))),
+    )];
+    mocks.push(mock_rpc_result(
+        "state_getStorage",
+        json!([era_key("ErasValidatorReward", 997)]),
+        json!(null),
+    ));
+    for (era, reward) in [(998u32, 100u128), (999, 200)] {
+        mocks.push(mock_rpc_result(
+            "state_getStorage",
+            json!([era_key("ErasValidatorReward", era)]),
+            json!(format!("0x{}", hex::encode(reward.encode()))),
+        ));
+        mocks.push(mock_rpc_result(
+            "state_getStorage",
+            json!([era_key("ErasTotalStake", era)]),
+            json!(format!("0x{}", hex::encode(365_000u128.encode()))),
+        ));
+    }
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let aprs = get_recent_era_aprs(&rpc, &metadata, 3, None).await?;
+
+    for mock in mocks {
+        mock.assert();
+    }
+    assert_eq!(
+        aprs.iter().map(|a| a.era).collect::<Vec<_>>(),
+        vec![998, 999]
+    );
+    assert_eq!(
+        aprs[0].start,
+        Some(NaiveDate::from_ymd(2023, 1, 8).and_hms(0, 0, 0))
+    );
+    assert_eq!(aprs[1].validator_reward, Balance::from(200));
+    assert!((aprs[1].apr - 0.2).abs() < 1e-12);
+    assert!((mean_apr(&aprs).unwrap() - 0.15).abs() < 1e-12);
+    assert_eq!(mean_apr(&[]), None);
+    Ok(())
+}
+
+const STASH: &str = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+const CONTROLLER: &str = "14Gjs1TD93gnwEBfDMHoCgsuf1s2TVKUP6Z1qKmAZnZ8cW5q";
+
+fn hex_result(value: impl Encode) -> serde_json::Value {
+    json!(format!("0x{}", hex::encode(value.encode())))
+}
+
+#[tokio::test]
+async fn staking_state_is_found_through_controller() -> Result<(), Box<dyn std::error::Error>> {
+    let stash = AccountId32::from_string(STASH)?;
+    let controller = AccountId32::from_string(CONTROLLER)?;
+    // The stash, total, active and unlocking chunks, followed by claimed rewards
+    let ledger = (
+        stash.clone(),
+        Compact(30_000_000_000u128),
+        Compact(20_000_000_000u128),
+        vec![(Compact(10_000_000_000u128), Compact(1028u32))],
+        vec![998u32, 999],
+    );
+    let metadata = test_metadata();
+    let mocks = vec![
+        mock_rpc_result(
+            "state_getStorage",
+            json!([metadata.storage_key("Staking", "Bonded", &[STASH])?]),
+            hex_result(&controller),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([metadata.storage_key("Staking", "Ledger", &[CONTROLLER])?]),
+            hex_result(ledger),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([metadata.storage_key("Staking", "Payee", &[STASH])?]),
+            hex_result((3u8, &controller)),
+        ),
+    ];
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let state = get_staking_state(&rpc, &metadata, STASH, None).await?;
+
+    for mock in mocks {
+        mock.assert();
+    }
+    assert_eq!(state.controller, controller);
+    assert_eq!(state.ledger.stash, stash);
+    assert_eq!(state.ledger.total, Balance::from(30_000_000_000));
+    assert_eq!(state.ledger.active, Balance::from(20_000_000_000));
+    assert_eq!(
+        state.ledger.unlocking,
+        vec![UnlockChunk {
+            value: Balance::from(10_000_000_000),
+            era: 1028
+        }]
+    );
+    assert_eq!(state.payee, RewardDestination::Account(controller));
+
+    let dot = ChainProfile {
+        ss58_format: 0,
+        token_symbol: "DOT".into(),
+        token_decimals: 10,
+    };
+    assert_eq!(state.payee.describe(&dot), CONTROLLER);
+    let active_era = ActiveEra {
+        index: 1025,
+        start: Some(NaiveDate::from_ymd(2023, 1, 10).and_hms(0, 0, 0)),
+    };
+    let status = crate::StakingStatus::new(&state, &active_era, DAY_MILLIS, &dot);
+    assert_eq!(status.unlocking[0].amount.tokens, "1.0000000000");
+    assert_eq!(status.unlocking[0].eras_left, 3);
+    assert_eq!(
+        status.unlocking[0].withdrawable_at,
+        Some(NaiveDate::from_ymd(2023, 1, 13).and_hms(0, 0, 0))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn unbonded_stash_is_reported() {
+    let mock = mock_rpc_result("state_getStorage", json!([]), json!(null));
+    let rpc = RpcClient::new(&mockito::server_url());
+    assert!(matches!(
+        get_staking_state(&rpc, &test_metadata(), STASH, None).await,
+        Err(ScError::NotBonded(_))
+    ));
+    mock.assert();
+}
+
+#[tokio::test]
+async fn nomination_targets_show_commission_activity_and_name(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let named = AccountId32::from_string(STASH)?;
+    let chilled = AccountId32::from_string(CONTROLLER)?;
+    let nominations = Nominations {
+        targets: vec![named.clone(), chilled.clone()],
+        submitted_in: 990,
+        suppressed: false,
+    };
+    let active_era = ActiveEra {
+        index: 1000,
+        start: None,
+    };
+    let metadata = test_metadata();
+    let exposure_key = |storage_name, validator| {
+        metadata
+            .storage_key("Staking", storage_name, &["1000", validator])
+            .unwrap()
+    };
+    let identity_key = |validator| {
+        metadata
+            .storage_key("Identity", "IdentityOf", &[validator])
+            .unwrap()
+    };
+    let validators_key = |validator| {
+        metadata
+            .storage_key("Staking", "Validators", &[validator])
+            .unwrap()
+    };
+    // A FeePaid judgement, a deposit, no additional fields, then a raw display name
+    let mut registration = (vec![(0u32, 1u8, 5u128)], 0u128, Compact(0u32)).encode();
+    registration.push(1 + "Validator One".len() as u8);
+    registration.extend_from_slice(b"Validator One");
+    // Legal name, web and the rest of the info follow
+    registration.extend_from_slice(&[0, 0, 0]);
+
+    let mocks = vec![
+        mock_rpc_result(
+            "state_getStorage",
+            json!([identity_key(STASH)]),
+            json!(format!("0x{}", hex::encode(&registration))),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([validators_key(STASH)]),
+            hex_result((Compact(50_000_000u32), false)),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([exposure_key("ErasStakersOverview", STASH)]),
+            hex_result((Compact(1_000u128), Compact(10u128), 3u32, 1u32)),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([identity_key(CONTROLLER)]),
+            json!(null),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([validators_key(CONTROLLER)]),
+            json!(null),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([exposure_key("ErasStakersOverview", CONTROLLER)]),
+            json!(null),
+        ),
+        mock_rpc_result(
+            "state_getStorage",
+            json!([exposure_key("ErasStakers", CONTROLLER)]),
+            json!(null),
+        ),
+    ];
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let targets = get_nomination_targets(&rpc, &metadata, &nominations, &active_era, None).await?;
+
+    for mock in mocks {
+        mock.assert();
+    }
+    assert_eq!(
+        targets,
+        vec![
+            NominationTarget {
+                validator: named,
+                name: Some("Validator One".into()),
+                prefs: Some(ValidatorPrefs {
+                    commission: 50_000_000,
+                    blocked: false
+                }),
+                active: true,
+            },
+            NominationTarget {
+                validator: chilled,
+                name: None,
+                prefs: None,
+                active: false,
+            },
+        ]
+    );
+    assert_eq!(targets[0].prefs.unwrap().commission_percent(), 5.);
+    Ok(())
+}
+
+#[tokio::test]
+async fn storage_missing_from_the_runtime_is_skipped() -> Result<(), Box<dyn std::error::Error>> {
+    let validator = AccountId32::from_string(STASH)?;
+    // A relay chain whose identities moved to a parachain, from before paged exposures
+    let pallets = vec![pallet(
+        "Staking",
+        7,
+        vec![map_entry::<(u32, AccountId32), u8>(
+            "ErasStakers",
+            vec![StorageHasher::Twox64Concat, StorageHasher::Twox64Concat],
+        )],
+    )];
+    let metadata = metadata_of(pallets);
+    let mock = mock_rpc_result(
+        "state_getStorage",
+        json!([metadata.storage_key("Staking", "ErasStakers", &["1000", STASH])?]),
+        hex_result((Compact(1_000u128), Compact(10u128), Vec::<u8>::new())),
+    );
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    assert_eq!(
+        get_identity_name(&rpc, &metadata, &validator, None).await?,
+        None
+    );
+    assert!(is_active_validator(&rpc, &metadata, 1000, &validator, None).await?);
+    mock.assert();
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+
+use super::{BalanceSnapshot, Store, SyncCursor};
+use crate::{Reward, ScError, StakeChange};
+
+/// Schema changes, applied in order.
+/// The database's user_version tells how many of them it has seen.
+pub(super) const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE rewards (
+        id TEXT UNIQUE,
+        date TEXT NOT NULL,
+        balance TEXT NOT NULL
+    );
+    CREATE TABLE stake_changes (
+        id TEXT UNIQUE,
+        timestamp TEXT NOT NULL,
+        accumulated_amount TEXT NOT NULL
+    );
+    CREATE TABLE balance_snapshots (
+        at TEXT NOT NULL,
+        block TEXT NOT NULL,
+        free TEXT NOT NULL,
+        reserved TEXT NOT NULL,
+        misc_frozen TEXT NOT NULL,
+        fee_frozen TEXT NOT NULL
+    );
+    CREATE TABLE sync_cursors (
+        name TEXT PRIMARY KEY,
+        at TEXT NOT NULL
+    );
+",
+    "
+    CREATE TABLE pool_rewards (
+        id TEXT UNIQUE,
+        date TEXT NOT NULL,
+        balance TEXT NOT NULL
+    );
+",
+];
+
+/// Dates and balances are stored as text.
+/// Dates sort correctly that way, and balances may not fit in an sqlite integer.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(file: impl AsRef<Path>) -> Result<Self, ScError> {
+        let mut conn = Connection::open(file)?;
+        migrate(&mut conn)?;
+        Ok(SqliteStore { conn })
+    }
+
+    pub fn schema_version(&self) -> Result<usize, ScError> {
+        Ok(self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?)
+    }
+
+    // Staking rewards and pool rewards are kept in tables of the same shape
+    fn rewards_in(&self, table: &str) -> Result<Vec<Reward>, ScError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT date, balance, id FROM {table} ORDER BY date, rowid"
+        ))?;
+        let rewards = stmt
+            .query_map([], |row| {
+                Ok(Reward {
+                    date: parsed(row, 0)?,
+                    balance: parsed::<u128>(row, 1)?.into(),
+                    id: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(rewards)
+    }
+
+    fn add_rewards_to(&mut self, table: &str, news: &[Reward]) -> Result<usize, ScError> {
+        let tx = self.conn.transaction()?;
+        let mut added = 0;
+        for new in news {
+            added += tx.execute(
+                &format!("INSERT OR IGNORE INTO {table} (date, balance, id) VALUES (?1, ?2, ?3)"),
+                params![
+                    format!("{:?}", new.date),
+                    new.balance.planck.to_string(),
+                    new.id
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), ScError> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn parsed<T>(row: &Row, idx: usize) -> rusqlite::Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    row.get::<_, String>(idx)?
+        .parse()
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+impl Store for SqliteStore {
+    fn rewards(&self) -> Result<Vec<Reward>, ScError> {
+        self.rewards_in("rewards")
+    }
+
+    fn pool_rewards(&self) -> Result<Vec<Reward>, ScError> {
+        self.rewards_in("pool_rewards")
+    }
+
+    fn stake_changes(&self) -> Result<Vec<StakeChange>, ScError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, accumulated_amount, id FROM stake_changes \
+            ORDER BY timestamp, rowid",
+        )?;
+        let stake_changes = stmt
+            .query_map([], |row| {
+                Ok(StakeChange {
+                    timestamp: parsed(row, 0)?,
+                    accumulated_amount: parsed::<u128>(row, 1)?.into(),
+                    id: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(stake_changes)
+    }
+
+    fn balance_snapshots(&self) -> Result<Vec<BalanceSnapshot>, ScError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT at, block, free, reserved, misc_frozen, fee_frozen FROM balance_snapshots \
+            ORDER BY at, rowid",
+        )?;
+        let snapshots = stmt
+            .query_map([], |row| {
+                Ok(BalanceSnapshot {
+                    at: parsed(row, 0)?,
+                    block: row.get(1)?,
+                    free: parsed(row, 2)?,
+                    reserved: parsed(row, 3)?,
+                    misc_frozen: parsed(row, 4)?,
+                    fee_frozen: parsed(row, 5)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(snapshots)
+    }
+
+    fn add_rewards(&mut self, news: &[Reward]) -> Result<usize, ScError> {
+        self.add_rewards_to("rewards", news)
+    }
+
+    fn add_pool_rewards(&mut self, news: &[Reward]) -> Result<usize, ScError> {
+        self.add_rewards_to("pool_rewards", news)
+    }
+
+    fn add_stake_changes(&mut self, news: &[StakeChange]) -> Result<usize, ScError> {
+        let tx = self.conn.transaction()?;
+        let mut added = 0;
+        for new in news {
+            added += tx.execute(
+                "INSERT OR IGNORE INTO stake_changes (timestamp, accumulated_amount, id) \
+                VALUES (?1, ?2, ?3)",
+                params![
+                    format!("{:?}", new.timestamp),
+                    new.accumulated_amount.planck.to_string(),
+                    new.id
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    fn add_balance_snapshot(&mut self, snapshot: &BalanceSnapshot) -> Result<(), ScError> {
+        self.conn.execute(
+            "INSERT INTO balance_snapshots (at, block, free, reserved, misc_frozen, fee_frozen) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                format!("{:?}", snapshot.at),
+                snapshot.block,
+                snapshot.free.to_string(),
+                snapshot.reserved.to_string(),
+                snapshot.misc_frozen.to_string(),
+                snapshot.fee_frozen.to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn sync_cursor(&self, cursor: SyncCursor) -> Result<Option<NaiveDateTime>, ScError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT at FROM sync_cursors WHERE name = ?1")?;
+        let mut rows = stmt.query_map([cursor.name()], |row| parsed(row, 0))?;
+        Ok(rows.next().transpose()?)
+    }
+
+    fn set_sync_cursor(&mut self, cursor: SyncCursor, at: NaiveDateTime) -> Result<(), ScError> {
+        self.conn.execute(
+            "INSERT INTO sync_cursors (name, at) VALUES (?1, ?2) \
+            ON CONFLICT(name) DO UPDATE SET at = excluded.at",
+            params![cursor.name(), format!("{:?}", at)],
+        )?;
+        Ok(())
+    }
+}
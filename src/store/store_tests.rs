@@ -0,0 +1,125 @@
+use super::*;
+use chrono::NaiveDate;
+
+fn reward(day: u32, balance: u128, id: Option<&str>) -> Reward {
+    Reward {
+        date: NaiveDate::from_ymd(2022, 9, day).and_hms(12, 0, 0),
+        balance: balance.into(),
+        id: id.map(String::from),
+    }
+}
+
+fn snapshot(day: u32, free: u128) -> BalanceSnapshot {
+    BalanceSnapshot {
+        at: NaiveDate::from_ymd(2022, 9, day).and_hms(12, 0, 0),
+        block: "0x01".into(),
+        free,
+        reserved: 2,
+        misc_frozen: 3,
+        fee_frozen: 4,
+    }
+}
+
+#[test]
+fn sqlite_store_migrates_once() -> Result<(), Box<dyn std::error::Error>> {
+    let file = testfile::generate_name();
+    let _tf = testfile::from_file(&file);
+
+    let store = SqliteStore::open(&file)?;
+    assert_eq!(store.schema_version()?, sqlite::MIGRATIONS.len());
+    drop(store);
+    // Reopening must not apply the migrations again
+    let store = SqliteStore::open(&file)?;
+    assert_eq!(store.schema_version()?, sqlite::MIGRATIONS.len());
+    Ok(())
+}
+
+#[test]
+fn sqlite_store_keeps_records_in_order() -> Result<(), Box<dyn std::error::Error>> {
+    let file = testfile::generate_name();
+    let _tf = testfile::from_file(&file);
+    let mut store = SqliteStore::open(&file)?;
+
+    let rewards = vec![
+        reward(20, 2, Some("200-1")),
+        reward(19, 1, None),
+        reward(20, 2, Some("200-2")),
+    ];
+    assert_eq!(store.add_rewards(&rewards)?, 3);
+    // The same ids are not added twice
+    assert_eq!(store.add_rewards(&rewards[2..])?, 0);
+    assert_eq!(
+        store.rewards()?,
+        vec![rewards[1].clone(), rewards[0].clone(), rewards[2].clone()]
+    );
+
+    let stake_changes = vec![StakeChange {
+        timestamp: NaiveDate::from_ymd(2022, 9, 19).and_hms(17, 53, 20),
+        accumulated_amount: u128::MAX.into(),
+        id: Some("100-1".into()),
+    }];
+    assert_eq!(store.add_stake_changes(&stake_changes)?, 1);
+    assert_eq!(store.stake_changes()?, stake_changes);
+
+    // Pool rewards are kept apart from staking rewards
+    assert_eq!(store.add_pool_rewards(&rewards[..1])?, 1);
+    assert_eq!(store.pool_rewards()?, vec![rewards[0].clone()]);
+    assert_eq!(store.rewards()?.len(), 3);
+
+    store.add_balance_snapshot(&snapshot(21, 5))?;
+    store.add_balance_snapshot(&snapshot(20, 4))?;
+    assert_eq!(
+        store.balance_snapshots()?,
+        vec![snapshot(20, 4), snapshot(21, 5)]
+    );
+    Ok(())
+}
+
+#[test]
+fn sqlite_store_keeps_sync_cursors() -> Result<(), Box<dyn std::error::Error>> {
+    let file = testfile::generate_name();
+    let _tf = testfile::from_file(&file);
+    let mut store = SqliteStore::open(&file)?;
+
+    assert_eq!(store.sync_cursor(SyncCursor::Rewards)?, None);
+    let first = NaiveDate::from_ymd(2022, 9, 19).and_hms(17, 53, 20);
+    let second = NaiveDate::from_ymd(2022, 9, 20).and_hms(17, 53, 20);
+    store.set_sync_cursor(SyncCursor::Rewards, first)?;
+    store.set_sync_cursor(SyncCursor::Rewards, second)?;
+    assert_eq!(store.sync_cursor(SyncCursor::Rewards)?, Some(second));
+    assert_eq!(store.sync_cursor(SyncCursor::StakeChanges)?, None);
+    Ok(())
+}
+
+#[test]
+fn csv_store_cursor_is_newest_record() -> Result<(), Box<dyn std::error::Error>> {
+    let rewards_file = testfile::generate_name();
+    let _rf = testfile::from_file(&rewards_file);
+    let balances_file = testfile::generate_name();
+    let _bf = testfile::from_file(&balances_file);
+    let mut store = CsvStore::new(rewards_file.to_str().unwrap(), "")
+        .with_balances_file(balances_file.to_str().unwrap());
+
+    let rewards = vec![reward(19, 1, None), reward(20, u128::MAX, Some("200-1"))];
+    assert_eq!(store.add_rewards(&rewards)?, 2);
+    assert_eq!(store.rewards()?, rewards);
+    assert_eq!(
+        store.sync_cursor(SyncCursor::Rewards)?,
+        Some(rewards[1].date)
+    );
+    assert_eq!(store.sync_cursor(SyncCursor::StakeChanges)?, None);
+    // Without a file for them, pool rewards can't be kept
+    assert!(matches!(
+        store.add_pool_rewards(&rewards),
+        Err(ScError::MissingEnvVariable(var)) if var == "KNOWN_POOL_REWARDS_FILE"
+    ));
+    assert_eq!(store.pool_rewards()?, vec![]);
+
+    store.add_balance_snapshot(&snapshot(20, u128::MAX))?;
+    store.add_balance_snapshot(&snapshot(21, 5))?;
+    assert_eq!(
+        store.balance_snapshots()?,
+        vec![snapshot(20, u128::MAX), snapshot(21, 5)]
+    );
+    Ok(())
+}
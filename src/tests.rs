@@ -3,24 +3,16 @@ use chrono::NaiveDate;
 
 use std::io::Write;
 
-use mockito::mock;
-
-#[test]
-fn check_with_decimal_point_strings() {
-    assert_eq!("123".with_decimal_point(10), "0.0000000123");
-    assert_eq!("12345678905".with_decimal_point(10), "1.2345678905");
-    assert_eq!("1234567890".with_decimal_point(10), "0.1234567890");
-}
-
-#[test]
-fn check_with_decimal_point_u128() {
-    // As long as u128 is the only candidate type to implement this trait,
-    // Rust will actually take a guess for us here...
-    //assert_eq!(123u128.with_decimal_point(), "0.0000000123");
-    assert_eq!(123.with_decimal_point(10), "0.0000000123");
-    assert_eq!(12345678905.with_decimal_point(10), "1.2345678905");
-    assert_eq!(1234567890.with_decimal_point(10), "0.1234567890");
-}
+use frame_metadata::v14::{
+    ExtrinsicMetadata, PalletConstantMetadata, PalletMetadata, PalletStorageMetadata,
+    RuntimeMetadataV14, StorageEntryMetadata, StorageEntryModifier, StorageEntryType,
+    StorageHasher,
+};
+use frame_metadata::RuntimeMetadataPrefixed;
+use mockito::{mock, Matcher, Mock};
+use parity_scale_codec::Encode;
+use scale_info::{meta_type, TypeInfo};
+use sp_core::crypto::AccountId32;
 
 #[test]
 fn read_known_stake_changes() -> Result<(), Box<dyn std::error::Error>> {
@@ -37,15 +29,220 @@ async fn get_total_issuance_happy_case() -> Result<(), Box<dyn std::error::Error
             "{ \"id\": 1, \"jsonrpc\": \"2.0\", \"result\": \"0x8a90a53a59f376aa0000000000000000\"}",
         )
         .create();
-    let rpc_endpoint = mockito::server_url();
+    let rpc = RpcClient::new(&mockito::server_url());
 
-    let total_issuance = get_total_issuance(&rpc_endpoint).await?;
+    let total_issuance = get_total_issuance(&rpc, &runtime_metadata(), None).await?;
 
     mock.assert();
     assert_eq!(total_issuance, 12283272598261174410);
     Ok(())
 }
 
+#[tokio::test]
+async fn get_total_issuance_at_block_number() -> Result<(), Box<dyn std::error::Error>> {
+    let block_hash = "0x91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3";
+    let hash_mock = mock("POST", "/")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "method": "chain_getBlockHash",
+            "params": [1000],
+        })))
+        .with_status(200)
+        .with_header("content-type", "application/json;charset=utf-8")
+        .with_body(format!(
+            "{{ \"id\": 1, \"jsonrpc\": \"2.0\", \"result\": \"{block_hash}\"}}"
+        ))
+        .create();
+    let storage_mock = mock("POST", "/")
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "method": "state_getStorage",
+            "params": [runtime_metadata().storage_key("Balances", "TotalIssuance", &[])?, block_hash],
+        })))
+        .with_status(200)
+        .with_header("content-type", "application/json;charset=utf-8")
+        .with_body(
+            "{ \"id\": 1, \"jsonrpc\": \"2.0\", \"result\": \"0x00e40b54020000000000000000000000\"}",
+        )
+        .create();
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let at = resolve_block_hash(&rpc, "1000").await?;
+    let total_issuance = get_total_issuance(&rpc, &runtime_metadata(), Some(&at)).await?;
+
+    hash_mock.assert();
+    storage_mock.assert();
+    assert_eq!(total_issuance, 10000000000);
+    Ok(())
+}
+
+#[tokio::test]
+async fn account_info_of_invalid_address_is_an_error() {
+    let rpc = RpcClient::new(&mockito::server_url());
+    let res = get_account_info(&rpc, &runtime_metadata(), "not an address", None).await;
+    assert!(matches!(res, Err(ScError::InvalidPolkadotAddr(addr)) if addr == "not an address"));
+}
+
+pub(crate) fn plain_entry<V: TypeInfo + 'static>(name: &'static str) -> StorageEntryMetadata {
+    StorageEntryMetadata {
+        name,
+        modifier: StorageEntryModifier::Optional,
+        ty: StorageEntryType::Plain(meta_type::<V>()),
+        default: vec![],
+        docs: vec![],
+    }
+}
+
+pub(crate) fn map_entry<K: TypeInfo + 'static, V: TypeInfo + 'static>(
+    name: &'static str,
+    hashers: Vec<StorageHasher>,
+) -> StorageEntryMetadata {
+    StorageEntryMetadata {
+        name,
+        modifier: StorageEntryModifier::Optional,
+        ty: StorageEntryType::Map {
+            hashers,
+            key: meta_type::<K>(),
+            value: meta_type::<V>(),
+        },
+        default: vec![],
+        docs: vec![],
+    }
+}
+
+pub(crate) fn pallet(
+    name: &'static str,
+    index: u8,
+    entries: Vec<StorageEntryMetadata>,
+) -> PalletMetadata {
+    PalletMetadata {
+        name,
+        storage: Some(PalletStorageMetadata {
+            prefix: name,
+            entries,
+        }),
+        calls: None,
+        event: None,
+        constants: vec![],
+        error: None,
+        index,
+    }
+}
+
+pub(crate) fn constant<T: Encode + TypeInfo + 'static>(
+    name: &'static str,
+    value: T,
+) -> PalletConstantMetadata {
+    PalletConstantMetadata {
+        name,
+        ty: meta_type::<T>(),
+        value: value.encode(),
+        docs: vec![],
+    }
+}
+
+/// The SCALE encoded metadata of a runtime with these pallets, as the node gives it
+pub(crate) fn metadata_bytes(pallets: Vec<PalletMetadata>) -> Vec<u8> {
+    let extrinsic = ExtrinsicMetadata {
+        ty: meta_type::<()>(),
+        version: 4,
+        signed_extensions: vec![],
+    };
+    let prefixed: RuntimeMetadataPrefixed =
+        RuntimeMetadataV14::new(pallets, extrinsic, meta_type::<()>()).into();
+    prefixed.encode()
+}
+
+pub(crate) fn metadata_of(pallets: Vec<PalletMetadata>) -> Metadata {
+    Metadata::decode(&metadata_bytes(pallets)).unwrap()
+}
+
+/// The storage items read outside of pallet specific modules, with Polkadot's hashers
+pub(crate) fn runtime_metadata() -> Metadata {
+    metadata_of(vec![
+        pallet(
+            "System",
+            0,
+            vec![map_entry::<AccountId32, u128>(
+                "Account",
+                vec![StorageHasher::Blake2_128Concat],
+            )],
+        ),
+        pallet("Timestamp", 3, vec![plain_entry::<u64>("Now")]),
+        pallet("Balances", 5, vec![plain_entry::<u128>("TotalIssuance")]),
+    ])
+}
+
+pub(crate) fn mock_rpc_result(
+    method: &str,
+    params: serde_json::Value,
+    result: serde_json::Value,
+) -> Mock {
+    mock("POST", "/")
+        .match_body(Matcher::PartialJson(
+            serde_json::json!({ "method": method, "params": params }),
+        ))
+        .with_status(200)
+        .with_header("content-type", "application/json;charset=utf-8")
+        .with_body(serde_json::json!({ "id": 1, "jsonrpc": "2.0", "result": result }).to_string())
+        .create()
+}
+
+#[tokio::test]
+async fn block_before_date_finds_last_earlier_block() -> Result<(), Box<dyn std::error::Error>> {
+    let metadata = runtime_metadata();
+    // Blocks 1 to 8, one every 6 seconds after the epoch
+    let mut mocks = vec![
+        mock_rpc_result(
+            "chain_getHeader",
+            serde_json::Value::Null,
+            serde_json::json!({ "number": "0x8" }),
+        ),
+        mock_rpc_result(
+            "state_getMetadata",
+            serde_json::Value::Null,
+            serde_json::json!(format!(
+                "0x{}",
+                hex::encode(metadata_bytes(vec![pallet(
+                    "Timestamp",
+                    3,
+                    vec![plain_entry::<u64>("Now")]
+                )]))
+            )),
+        ),
+    ];
+    let timestamp_key = metadata.storage_key("Timestamp", "Now", &[])?;
+    for number in 0..=8u64 {
+        let hash = format!("0x{:064x}", number + 0xb10c);
+        let millis = format!("0x{}", hex::encode((number * 6000).encode()));
+        mocks.push(mock_rpc_result(
+            "chain_getBlockHash",
+            serde_json::json!([number]),
+            serde_json::json!(hash),
+        ));
+        mocks.push(mock_rpc_result(
+            "state_getStorage",
+            serde_json::json!([timestamp_key, hash]),
+            serde_json::json!(millis),
+        ));
+    }
+    let rpc = RpcClient::new(&mockito::server_url());
+
+    let date = NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 20);
+    let (number, hash) = block_before_date(&rpc, date).await?;
+    assert_eq!(number, 3);
+    assert_eq!(hash, format!("0x{:064x}", 3 + 0xb10c));
+
+    let date = NaiveDate::from_ymd(1970, 1, 1).and_hms(0, 0, 24);
+    let (number, _) = block_before_date(&rpc, date).await?;
+    assert_eq!(number, 3);
+
+    let date = NaiveDate::from_ymd(2023, 1, 1).and_hms(0, 0, 0);
+    let (number, _) = block_before_date(&rpc, date).await?;
+    assert_eq!(number, 8);
+
+    drop(mocks);
+    Ok(())
+}
+
 #[tokio::test]
 async fn get_stake_changes_happy_case() -> Result<(), Box<dyn std::error::Error>> {
     // Simulate a subquery server that says three rewards exist
@@ -66,7 +263,7 @@ async fn get_stake_changes_happy_case() -> Result<(), Box<dyn std::error::Error>
              }",
         )
         .create();
-    let subquery_endpoint = mockito::server_url();
+    let subquery_endpoint = SubqueryEndpoint::new(mockito::server_url());
 
     // Simulate two known data points
     let dummy_file_name = testfile::generate_name();
@@ -78,9 +275,10 @@ async fn get_stake_changes_happy_case() -> Result<(), Box<dyn std::error::Error>
     let _tf = testfile::from_file(&dummy_file_name); // Takes care of deleting tmp file
 
     let found_stake_changes = get_stake_changes(
-        &subquery_endpoint,
+        subquery_endpoint,
         "dummyAddress",
         dummy_file_name.to_str().unwrap(),
+        None,
     )
     .await?;
 
@@ -90,7 +288,8 @@ async fn get_stake_changes_happy_case() -> Result<(), Box<dyn std::error::Error>
         found_stake_changes[0],
         StakeChange {
             timestamp: NaiveDate::from_ymd(2022, 9, 19).and_hms(23, 26, 40),
-            accumulated_amount: 3000000000000
+            accumulated_amount: 3000000000000.into(),
+            id: None,
         }
     );
     Ok(())
@@ -129,6 +328,7 @@ async fn get_staking_rewards_happy_case() -> Result<(), Box<dyn std::error::Erro
         subquery_endpoint,
         "dummyAddress",
         dummy_file_name.to_str().unwrap(),
+        None,
     )
     .await?;
 
@@ -138,8 +338,366 @@ async fn get_staking_rewards_happy_case() -> Result<(), Box<dyn std::error::Erro
         found_rewards[0],
         Reward {
             date: NaiveDate::from_ymd(2016, 7, 8).and_hms(9, 10, 11),
-            balance: 11
+            balance: 11.into(),
+            id: None,
         }
     );
     Ok(())
 }
+
+#[tokio::test]
+async fn get_staking_rewards_follows_pages() -> Result<(), Box<dyn std::error::Error>> {
+    // Simulate a subquery server with three rewards, served two per page
+    let first_page = mock("POST", "/")
+        .match_body(Matcher::Regex("first: 2, orderBy".into()))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            "{\"data\":\
+                {\"stakingRewards\":\
+                    {\"nodes\":\
+                        [\
+                            {\"balance\":\"9\",\"date\":\"2015-06-10T08:07:06.011\"},\
+                            {\"balance\":\"10\",\"date\":\"2015-06-11T08:07:06.011\"}\
+                        ],\
+                     \"pageInfo\":{\"hasNextPage\":true,\"endCursor\":\"cursor-2\"}\
+                    }\
+                 }\
+             }",
+        )
+        .create();
+    let second_page = mock("POST", "/")
+        .match_body(Matcher::Regex("first: 2, after: .*cursor-2".into()))
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            "{\"data\":\
+                {\"stakingRewards\":\
+                    {\"nodes\":\
+                        [\
+                            {\"balance\":\"11\",\"date\":\"2016-07-08T09:10:11.000\"}\
+                        ],\
+                     \"pageInfo\":{\"hasNextPage\":false,\"endCursor\":\"cursor-3\"}\
+                    }\
+                 }\
+             }",
+        )
+        .create();
+    let subquery_endpoint = SubqueryEndpoint::new(mockito::server_url()).with_page_size(2);
+
+    let found_rewards =
+        get_staking_rewards(subquery_endpoint, "dummyAddress", "no_such_file.csv", None).await?;
+
+    first_page.assert();
+    second_page.assert();
+    assert_eq!(
+        found_rewards
+            .iter()
+            .map(|r| r.balance.planck)
+            .collect::<Vec<_>>(),
+        vec![9, 10, 11]
+    );
+    Ok(())
+}
+
+#[test]
+fn subquery_queries_filter_on_since() {
+    let since = NaiveDate::from_ymd(2022, 9, 19).and_hms(17, 53, 20);
+    let nova = SubqueryEndpoint::new(
+        "https://api.subquery.network/sq/nova-wallet/nova-wallet-polkadot".into(),
+    );
+    assert!(nova
+        .get_query("dummyAddress", Some(since), None)
+        .contains("timestamp: {greaterThanOrEqualTo: \"1663610000\"}"));
+    assert!(nova
+        .pool_rewards_query("dummyAddress", Some(since), None)
+        .contains("timestamp: {greaterThanOrEqualTo: \"1663610000\"}"));
+    let staking_sum = SubqueryEndpoint::new("https://staking-sum.example".into());
+    assert!(staking_sum
+        .get_query("dummyAddress", Some(since), None)
+        .contains("date: {greaterThanOrEqualTo: \"2022-09-19T17:53:20\"}"));
+    assert!(!staking_sum
+        .get_query("dummyAddress", None, None)
+        .contains("greaterThanOrEqualTo"));
+}
+
+#[tokio::test]
+async fn get_staking_rewards_is_idempotent() -> Result<(), Box<dyn std::error::Error>> {
+    // Two rewards share a timestamp, and only the first one is known
+    let mock = mock("POST", "/")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            "{\"data\":\
+                {\"stakingRewards\":\
+                    {\"nodes\":\
+                        [\
+                            {\"id\":\"100-1\",\"balance\":\"9\",\"date\":\"2015-06-10T08:07:06.011\"},\
+                            {\"id\":\"100-2\",\"balance\":\"9\",\"date\":\"2015-06-10T08:07:06.011\"},\
+                            {\"id\":\"200-1\",\"balance\":\"11\",\"date\":\"2016-07-08T09:10:11.000\"}\
+                        ]\
+                    }\
+                 }\
+             }",
+        )
+        .expect(2)
+        .create();
+
+    let dummy_file_name = testfile::generate_name();
+    let mut f = std::fs::File::create(&dummy_file_name).unwrap();
+    f.write_all("2015-06-10T08:07:06.011,9,100-1\n".as_bytes())
+        .expect("Failed to write to tmp file");
+    let _tf = testfile::from_file(&dummy_file_name);
+
+    let found_rewards = get_staking_rewards(
+        SubqueryEndpoint::new(mockito::server_url()),
+        "dummyAddress",
+        dummy_file_name.to_str().unwrap(),
+        None,
+    )
+    .await?;
+    assert_eq!(
+        found_rewards
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "2015-06-10T08:07:06,9,100-2",
+            "2016-07-08T09:10:11,11,200-1"
+        ]
+    );
+
+    // Once appended, a second sync finds nothing new
+    for reward in &found_rewards {
+        writeln!(f, "{reward}")?;
+    }
+    let found_rewards = get_staking_rewards(
+        SubqueryEndpoint::new(mockito::server_url()),
+        "dummyAddress",
+        dummy_file_name.to_str().unwrap(),
+        None,
+    )
+    .await?;
+
+    mock.assert();
+    assert!(found_rewards.is_empty());
+    Ok(())
+}
+
+#[test]
+fn append_to_known_file_completes_last_line() -> Result<(), Box<dyn std::error::Error>> {
+    let dummy_file_name = testfile::generate_name();
+    std::fs::write(&dummy_file_name, "2015-06-10T08:07:06,9,100-1")?;
+    let _tf = testfile::from_file(&dummy_file_name);
+
+    let news = vec![Reward {
+        date: NaiveDate::from_ymd(2016, 7, 8).and_hms(9, 10, 11),
+        balance: 11.into(),
+        id: Some("200-1".into()),
+    }];
+    assert_eq!(append_to_known_file(&dummy_file_name, &news)?, 1);
+    assert_eq!(
+        append_to_known_file(&dummy_file_name, &Vec::<Reward>::new())?,
+        0
+    );
+    assert_eq!(
+        std::fs::read_to_string(&dummy_file_name)?,
+        "2015-06-10T08:07:06,9,100-1\n2016-07-08T09:10:11,11,200-1\n"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn sync_stake_changes_creates_known_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mock = mock("POST", "/")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(
+            "{\"data\":\
+                {\"stakeChanges\":\
+                    {\"nodes\":\
+                        [\
+                            {\"id\":\"100-1\",\"timestamp\":\"1663610000\",\"accumulatedAmount\":\"1000\"},\
+                            {\"id\":\"200-1\",\"timestamp\":\"1663620000\",\"accumulatedAmount\":\"2000\"}\
+                        ]\
+                    }\
+                 }\
+             }",
+        )
+        .expect(2)
+        .create();
+
+    let dummy_file_name = testfile::generate_name();
+    let _tf = testfile::from_file(&dummy_file_name);
+    let dummy_file = dummy_file_name.to_str().unwrap();
+    let mut store = CsvStore::new("", dummy_file);
+
+    let added = sync_stake_changes(
+        SubqueryEndpoint::new(mockito::server_url()),
+        "dummyAddress",
+        &mut store,
+        None,
+    )
+    .await?;
+    assert_eq!(added, 2);
+
+    // Syncing again adds nothing
+    let added = sync_stake_changes(
+        SubqueryEndpoint::new(mockito::server_url()),
+        "dummyAddress",
+        &mut store,
+        None,
+    )
+    .await?;
+    mock.assert();
+    assert_eq!(added, 0);
+    assert_eq!(known_stake_changes(dummy_file)?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn accounts_are_named_address_pairs() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+    let accounts = parse_accounts(&format!("stash={addr}, other = {addr}"))?;
+    assert_eq!(
+        accounts,
+        vec![Account::new("stash", addr), Account::new("other", addr)]
+    );
+    assert_eq!(
+        accounts[0].file_name("data/known_rewards.csv"),
+        "data/known_rewards_stash.csv"
+    );
+    assert_eq!(accounts[1].file_name("known"), "known_other");
+
+    assert!(parse_accounts(&format!("stash={addr},stash={addr}")).is_err());
+    assert!(parse_accounts("stash=<your_address_here>").is_err());
+    assert!(parse_accounts(addr).is_err());
+    // Names become part of file names
+    for name in ["../x", "a/b", "a\\b", "a.b", "a b"] {
+        assert!(parse_accounts(&format!("{name}={addr}")).is_err());
+    }
+
+    let selected = select_accounts(accounts.clone(), Some("other"))?;
+    assert_eq!(selected, vec![accounts[1].clone()]);
+    assert!(select_accounts(accounts, Some("nobody")).is_err());
+    Ok(())
+}
+
+#[test]
+fn dates_and_days_parse() -> Result<(), ScError> {
+    let day = NaiveDate::from_ymd(2023, 1, 2);
+    assert_eq!(parse_date("2023-01-02T03:04:05")?, day.and_hms(3, 4, 5));
+    assert_eq!(parse_date("2023-01-02")?, day.and_hms(0, 0, 0));
+    assert!(matches!(
+        parse_date("2023-13-01"),
+        Err(ScError::InvalidDate(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn records_between_dates() {
+    let day = |day| NaiveDate::from_ymd(2022, 9, day).and_hms(12, 0, 0);
+    let reward = |d| Reward {
+        date: day(d),
+        balance: 1.into(),
+        id: None,
+    };
+    let change = |d, amount: u128| StakeChange {
+        timestamp: day(d),
+        accumulated_amount: amount.into(),
+        id: Some(format!("{d}")),
+    };
+
+    let rewards = vec![reward(1), reward(2), reward(3)];
+    assert_eq!(
+        rewards_between(rewards.clone(), Some(day(2)), Some(day(3))),
+        vec![reward(2)]
+    );
+    assert_eq!(rewards_between(rewards.clone(), None, None), rewards);
+
+    let changes = vec![change(1, 10), change(3, 30), change(5, 50)];
+    // The stake held when the range starts is moved to its start
+    assert_eq!(
+        stake_changes_between(changes.clone(), Some(day(2)), Some(day(5))),
+        vec![
+            StakeChange {
+                timestamp: day(2),
+                id: None,
+                ..change(1, 10)
+            },
+            change(3, 30)
+        ]
+    );
+    assert_eq!(
+        stake_changes_between(changes.clone(), Some(day(3)), None),
+        vec![change(3, 30), change(5, 50)]
+    );
+    assert_eq!(stake_changes_between(changes.clone(), None, None), changes);
+}
+
+#[test]
+fn aggregated_stake_sums_latest_of_each_account() {
+    let change = |hour, accumulated_amount: u128| StakeChange {
+        timestamp: NaiveDate::from_ymd(2022, 9, 19).and_hms(hour, 0, 0),
+        accumulated_amount: accumulated_amount.into(),
+        id: None,
+    };
+    let aggregated = aggregate_stake_changes(vec![
+        vec![change(1, 10), change(3, 30)],
+        vec![change(2, 5), change(4, 0)],
+    ]);
+    assert_eq!(
+        aggregated,
+        vec![change(1, 10), change(2, 15), change(3, 35), change(4, 30)]
+    );
+}
+
+#[test]
+fn chain_profile_from_system_properties() -> Result<(), Box<dyn std::error::Error>> {
+    let polkadot = ChainProfile::from_properties(&serde_json::from_str(
+        r#"{"ss58Format":0,"tokenDecimals":10,"tokenSymbol":"DOT"}"#,
+    )?);
+    assert_eq!(
+        polkadot,
+        ChainProfile {
+            ss58_format: 0,
+            token_symbol: "DOT".into(),
+            token_decimals: 10,
+        }
+    );
+    assert_eq!(polkadot.amount(15_000_000_000), "1.5000000000 DOT");
+
+    // The native token comes first on chains with several tokens
+    let acala = ChainProfile::from_properties(&serde_json::from_str(
+        r#"{"ss58Format":10,"tokenDecimals":[12,12],"tokenSymbol":["ACA","AUSD"]}"#,
+    )?);
+    assert_eq!(acala.token_symbol, "ACA");
+    assert_eq!(acala.token_decimals, 12);
+
+    let westend = ChainProfile::from_properties(&serde_json::from_str(
+        r#"{"tokenDecimals":12,"tokenSymbol":"WND"}"#,
+    )?);
+    assert_eq!(westend.ss58_format, 42);
+    Ok(())
+}
+
+#[test]
+fn chain_profile_checks_account_addresses() -> Result<(), Box<dyn std::error::Error>> {
+    let addr = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+    let kusama = ChainProfile {
+        ss58_format: 2,
+        token_symbol: "KSM".into(),
+        token_decimals: 12,
+    };
+    let kusama_addr = kusama.addr(&AccountId32::from_string(addr)?);
+
+    let accounts = parse_accounts(&format!("stash={kusama_addr}"))?;
+    kusama.check_accounts(&accounts)?;
+    let accounts = parse_accounts(&format!("stash={addr}"))?;
+    assert!(matches!(
+        kusama.check_accounts(&accounts),
+        Err(ScError::WrongChainAddr(_, 2))
+    ));
+    Ok(())
+}
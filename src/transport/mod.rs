@@ -0,0 +1,239 @@
+#[cfg(test)]
+mod transport_tests;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot, OnceCell};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::util;
+use crate::ScError;
+
+struct PendingRequest {
+    answer: oneshot::Sender<Value>,
+    // Present if the request opens a subscription
+    notifications: Option<mpsc::UnboundedSender<Value>>,
+}
+
+#[derive(Default)]
+struct Routes {
+    requests: HashMap<u64, PendingRequest>,
+    subscriptions: HashMap<String, mpsc::UnboundedSender<Value>>,
+}
+
+// Set to None by the reader task once the connection is gone
+type SharedRoutes = Arc<Mutex<Option<Routes>>>;
+
+/// A JSON-RPC client for a Substrate node.
+///
+/// `http://` and `https://` endpoints get one POST per call.
+/// `ws://` and `wss://` endpoints share a single connection across all calls,
+/// opened on the first request.
+pub enum RpcClient {
+    Http {
+        endpoint: String,
+        client: reqwest::Client,
+    },
+    Ws(WsClient),
+}
+
+impl RpcClient {
+    pub fn new(endpoint: &str) -> Self {
+        if endpoint.starts_with("ws://") || endpoint.starts_with("wss://") {
+            RpcClient::Ws(WsClient::new(endpoint))
+        } else {
+            RpcClient::Http {
+                endpoint: endpoint.into(),
+                client: reqwest::Client::new(),
+            }
+        }
+    }
+
+    pub async fn request<Params: Serialize>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<Value, ScError> {
+        match self {
+            RpcClient::Http { endpoint, client } => {
+                Ok(util::rpc(client, endpoint, method, params).await?)
+            }
+            RpcClient::Ws(ws) => ws.request(method, params).await,
+        }
+    }
+
+    /// Only WebSocket endpoints can push notifications to us.
+    pub async fn subscribe<Params: Serialize>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<Subscription, ScError> {
+        match self {
+            RpcClient::Http { .. } => Err(ScError::SubscriptionNeedsWebSocket),
+            RpcClient::Ws(ws) => ws.subscribe(method, params).await,
+        }
+    }
+}
+
+/// Notifications pushed by the node for one subscription.
+pub struct Subscription {
+    notifications: mpsc::UnboundedReceiver<Value>,
+}
+
+impl Subscription {
+    /// The `result` of the next notification.
+    /// None when the connection is gone.
+    pub async fn next(&mut self) -> Option<Value> {
+        let mut notification = self.notifications.recv().await?;
+        Some(notification["params"]["result"].take())
+    }
+}
+
+pub struct WsClient {
+    endpoint: String,
+    connection: OnceCell<WsConnection>,
+    next_id: AtomicU64,
+}
+
+struct WsConnection {
+    outgoing: mpsc::UnboundedSender<Message>,
+    routes: SharedRoutes,
+}
+
+impl WsClient {
+    fn new(endpoint: &str) -> Self {
+        WsClient {
+            endpoint: endpoint.into(),
+            connection: OnceCell::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    async fn connection(&self) -> Result<&WsConnection, ScError> {
+        self.connection
+            .get_or_try_init(|| WsConnection::open(&self.endpoint))
+            .await
+    }
+
+    async fn request<Params: Serialize>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<Value, ScError> {
+        let mut ans = self.send(method, params, None).await?;
+        Ok(ans["result"].take())
+    }
+
+    async fn subscribe<Params: Serialize>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<Subscription, ScError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let ans = self.send(method, params, Some(tx)).await?;
+        if ans["result"].is_null() {
+            return Err(ScError::NoDataFound);
+        }
+        Ok(Subscription { notifications: rx })
+    }
+
+    async fn send<Params: Serialize>(
+        &self,
+        method: &str,
+        params: Params,
+        notifications: Option<mpsc::UnboundedSender<Value>>,
+    ) -> Result<Value, ScError> {
+        let connection = self.connection().await?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (answer, rx) = oneshot::channel();
+        match connection.routes.lock().unwrap().as_mut() {
+            Some(routes) => routes.requests.insert(
+                id,
+                PendingRequest {
+                    answer,
+                    notifications,
+                },
+            ),
+            None => return Err(ScError::ConnectionClosed),
+        };
+
+        let req = json! {{
+            "id": id,
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }};
+        if connection
+            .outgoing
+            .send(Message::Text(req.to_string()))
+            .is_err()
+        {
+            return Err(ScError::ConnectionClosed);
+        }
+
+        rx.await.map_err(|_| ScError::ConnectionClosed)
+    }
+}
+
+impl WsConnection {
+    async fn open(endpoint: &str) -> Result<Self, ScError> {
+        let (stream, _) = tokio_tungstenite::connect_async(endpoint).await?;
+        let (mut sink, mut source) = stream.split();
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Message>();
+        let routes: SharedRoutes = Arc::new(Mutex::new(Some(Routes::default())));
+
+        tokio::spawn(async move {
+            while let Some(msg) = outgoing_rx.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let reader_routes = routes.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = source.next().await {
+                let text = match msg {
+                    Message::Text(text) => text,
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                let ans: Value = match serde_json::from_str(&text) {
+                    Ok(ans) => ans,
+                    Err(_) => continue,
+                };
+                let mut guard = reader_routes.lock().unwrap();
+                let routes = match guard.as_mut() {
+                    Some(routes) => routes,
+                    None => break,
+                };
+                if let Some(id) = ans["id"].as_u64() {
+                    if let Some(pending) = routes.requests.remove(&id) {
+                        // Register the subscription before reading further,
+                        // so that no early notification gets lost
+                        if let Some(notifications) = pending.notifications {
+                            routes
+                                .subscriptions
+                                .insert(ans["result"].to_string(), notifications);
+                        }
+                        let _ = pending.answer.send(ans);
+                    }
+                } else if let Some(subscription) = ans["params"].get("subscription") {
+                    if let Some(notifications) = routes.subscriptions.get(&subscription.to_string())
+                    {
+                        let _ = notifications.send(ans);
+                    }
+                }
+            }
+            // Dropping the senders wakes up everyone still waiting for an answer
+            reader_routes.lock().unwrap().take();
+        });
+
+        Ok(WsConnection { outgoing, routes })
+    }
+}
@@ -0,0 +1,149 @@
+use crate::tests::runtime_metadata;
+use crate::transport::RpcClient;
+use crate::*;
+
+use parity_scale_codec::Encode;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+
+// A local stand-in for a node's WebSocket endpoint.
+// Answers every request with the result registered for its method.
+// Subscription requests are followed by the given notifications.
+async fn ws_stand_in(
+    results: Vec<(&'static str, Value)>,
+    notifications: Vec<Value>,
+) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let results = results.clone();
+            let notifications = notifications.clone();
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let req: Value = serde_json::from_str(&text).unwrap();
+                    let result = results
+                        .iter()
+                        .find(|(method, _)| req["method"] == *method)
+                        .map(|(_, result)| result.clone())
+                        .unwrap_or(Value::Null);
+                    let ans = json!({"id": req["id"], "jsonrpc": "2.0", "result": result});
+                    ws.send(Message::Text(ans.to_string())).await.unwrap();
+                    if req["method"].as_str().unwrap().contains("_subscribe") {
+                        for notification in &notifications {
+                            let notification = json!({
+                                "jsonrpc": "2.0",
+                                "method": "state_storage",
+                                "params": {"subscription": result, "result": notification},
+                            });
+                            ws.send(Message::Text(notification.to_string()))
+                                .await
+                                .unwrap();
+                        }
+                    }
+                }
+            });
+        }
+    });
+    (url, connections)
+}
+
+#[tokio::test]
+async fn ws_requests_share_one_connection() -> Result<(), Box<dyn std::error::Error>> {
+    let (url, connections) = ws_stand_in(
+        vec![
+            (
+                "state_getStorage",
+                json!("0x8a90a53a59f376aa0000000000000000"),
+            ),
+            ("system_properties", json!({"tokenDecimals": 10})),
+        ],
+        vec![],
+    )
+    .await;
+    let rpc = RpcClient::new(&url);
+
+    let total_issuance = get_total_issuance(&rpc, &runtime_metadata(), None).await?;
+    let properties = system_properties(&rpc).await?;
+
+    assert_eq!(total_issuance, 12283272598261174410);
+    assert!(properties.contains("\"tokenDecimals\": 10"));
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn ws_concurrent_requests_get_their_own_answers() -> Result<(), Box<dyn std::error::Error>> {
+    let (url, _) = ws_stand_in(
+        vec![
+            ("chain_getBlockHash", json!("0x1234")),
+            ("system_chain", json!("Polkadot")),
+        ],
+        vec![],
+    )
+    .await;
+    let rpc = RpcClient::new(&url);
+
+    let (hash, chain) = tokio::join!(
+        rpc.request("chain_getBlockHash", ()),
+        rpc.request("system_chain", ())
+    );
+
+    assert_eq!(hash?, json!("0x1234"));
+    assert_eq!(chain?, json!("Polkadot"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn watch_account_info_follows_changes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut account_info = PolkadotAccountInfo::default();
+    account_info.data.free = 10.into();
+    let first = format!("0x{}", hex::encode(account_info.encode()));
+    account_info.data.free = 25.into();
+    let second = format!("0x{}", hex::encode(account_info.encode()));
+    let addr = "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD";
+    let metadata = runtime_metadata();
+    let key = metadata.storage_key("System", "Account", &[addr])?;
+    let (url, _) = ws_stand_in(
+        vec![("state_subscribeStorage", json!("sub-1"))],
+        vec![
+            json!({"block": "0xaa", "changes": [[key, first]]}),
+            json!({"block": "0xbb", "changes": [[key, second]]}),
+            json!({"block": "0xcc", "changes": [[key, null]]}),
+        ],
+    )
+    .await;
+    let rpc = RpcClient::new(&url);
+
+    let mut watch = watch_account_info(&rpc, &metadata, addr).await?;
+
+    let (block, info) = watch.next().await.unwrap()?;
+    assert_eq!((block.as_str(), info.data.free.planck), ("0xaa", 10));
+    let (block, info) = watch.next().await.unwrap()?;
+    assert_eq!((block.as_str(), info.data.free.planck), ("0xbb", 25));
+    let (block, info) = watch.next().await.unwrap()?;
+    assert_eq!((block.as_str(), info.data.free.planck), ("0xcc", 0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn http_endpoint_cannot_subscribe() {
+    let rpc = RpcClient::new("http://127.0.0.1:1");
+    let res = watch_account_info(
+        &rpc,
+        &runtime_metadata(),
+        "16ZL8yLyXv3V3L3z9ofR1ovFLziyXaN1DPq4yffMAZ9czzBD",
+    )
+    .await;
+    assert!(matches!(res, Err(ScError::SubscriptionNeedsWebSocket)));
+}
@@ -1,17 +1,19 @@
 #[cfg(test)]
 mod util_tests;
 
+use std::fmt;
+use std::num::IntErrorKind;
+
 use chrono::NaiveDateTime;
-use serde::{de, Deserialize, Deserializer, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{json, Value};
 
 pub async fn rpc<Params: Serialize>(
+    client: &reqwest::Client,
     rpc_endpoint: &str,
     method: &str,
     params: Params,
 ) -> Result<Value, reqwest::Error> {
-    let client = reqwest::Client::new();
-
     let resp = client
         .post(rpc_endpoint)
         .json(&json! {{
@@ -43,6 +45,31 @@ pub async fn subquery<Params: Serialize>(
     Ok(ans["data"].take())
 }
 
+/// Follows the `pageInfo` cursors of a paginated query,
+/// and collects the `nodes` of every page.
+pub async fn subquery_pages(
+    endpoint: &str,
+    query_root: &str,
+    query_after: impl Fn(Option<&str>) -> String,
+) -> Result<Vec<Value>, reqwest::Error> {
+    let mut nodes = vec![];
+    let mut after: Option<String> = None;
+    loop {
+        let mut ans = subquery(endpoint, query_after(after.as_deref())).await?;
+        let page = ans[query_root].take();
+        if let Value::Array(page_nodes) = &page["nodes"] {
+            nodes.extend(page_nodes.iter().cloned());
+        }
+        match (
+            page["pageInfo"]["hasNextPage"].as_bool(),
+            page["pageInfo"]["endCursor"].as_str(),
+        ) {
+            (Some(true), Some(cursor)) => after = Some(cursor.into()),
+            _ => return Ok(nodes),
+        }
+    }
+}
+
 // https://stackoverflow.com/questions/57614558/how-to-use-a-custom-serde-deserializer-for-chrono-timestamps
 pub fn naive_date_time_from_str<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
 where
@@ -59,20 +86,124 @@ where
     }
 }
 
-// https://users.rust-lang.org/t/deserialize-a-number-that-may-be-inside-a-string-serde-json/27318
-// A custom deserializer, since the value sometimes appear as a quoted string
+/// A custom deserializer, since balances appear as json numbers, as quoted decimal strings,
+/// or as 0x-prefixed hex strings. All of u128 is read without loss.
 pub fn balance_from_maybe_str<'de, D>(deserializer: D) -> Result<u128, D::Error>
 where
     D: Deserializer<'de>,
 {
-    use serde::de::Error;
-    let v = Value::deserialize(deserializer)?;
-    let an_u64 = v
-        .as_u64()
-        .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
-        .ok_or_else(|| D::Error::custom("non-integer"))?;
-    let an_u128 = an_u64
-        .try_into()
-        .map_err(|_| D::Error::custom("overflow"))?;
-    Ok(an_u128)
+    deserializer.deserialize_any(BalanceVisitor { field: "balance" })
+}
+
+/// Balance deserializers that name their field in errors
+pub mod balance_field {
+    use super::BalanceVisitor;
+    use serde::Deserializer;
+
+    macro_rules! balance_fields {
+        ($($name:ident => $field:literal),* $(,)?) => {$(
+            pub fn $name<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+            where
+                D: Deserializer<'de>,
+                T: From<u128>,
+            {
+                deserializer
+                    .deserialize_any(BalanceVisitor { field: $field })
+                    .map(T::from)
+            }
+        )*};
+    }
+
+    balance_fields! {
+        balance => "balance",
+        accumulated_amount => "accumulatedAmount",
+        free => "free",
+        reserved => "reserved",
+        misc_frozen => "misc_frozen",
+        fee_frozen => "fee_frozen",
+    }
+}
+
+struct BalanceVisitor {
+    field: &'static str,
+}
+
+impl BalanceVisitor {
+    fn parse<E: de::Error>(&self, value: &str) -> Result<u128, E> {
+        let digits = value.trim();
+        let parsed = match digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            Some(hex) => u128::from_str_radix(hex, 16),
+            None => digits.parse(),
+        };
+        parsed.map_err(|err| match err.kind() {
+            IntErrorKind::PosOverflow => self.overflow(value),
+            _ => E::custom(format!(
+                "{} {value} is not a whole number of planck",
+                self.field
+            )),
+        })
+    }
+
+    fn overflow<E: de::Error>(&self, value: impl fmt::Display) -> E {
+        E::custom(format!("{} {value} overflows u128", self.field))
+    }
+}
+
+impl<'de> de::Visitor<'de> for BalanceVisitor {
+    type Value = u128;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} as a whole number of planck, in a number or a decimal or 0x-prefixed hex string",
+            self.field
+        )
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u128, E> {
+        Ok(value.into())
+    }
+
+    fn visit_u128<E: de::Error>(self, value: u128) -> Result<u128, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<u128, E> {
+        self.visit_i128(value.into())
+    }
+
+    fn visit_i128<E: de::Error>(self, value: i128) -> Result<u128, E> {
+        value
+            .try_into()
+            .map_err(|_| E::custom(format!("{} {value} is negative", self.field)))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<u128, E> {
+        Err(E::custom(format!(
+            "{} {value} is not a whole number of planck",
+            self.field
+        )))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<u128, E> {
+        self.parse(value)
+    }
+
+    // Json numbers too large for any integer come as a map that serde_json reads back
+    // as a Number, and other maps are rejected
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<u128, A::Error> {
+        let number = serde_json::Number::deserialize(de::value::MapAccessDeserializer::new(map))?;
+        self.parse(&number.to_string())
+    }
+}
+
+/// Serializes a planck amount as a string, like a [`crate::Balance`]
+pub fn balance_to_str<S>(balance: &u128, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_str(balance)
 }
@@ -18,3 +18,51 @@ fn read_date_missing_millisecond() -> Result<(), Box<dyn std::error::Error>> {
     assert_eq!(back_to_str, "2022-04-01T18:27:12");
     Ok(())
 }
+
+#[test]
+fn balances_keep_all_of_u128() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::util::balance_from_maybe_str;
+    let balance = |json: &str| -> Result<u128, serde_json::Error> {
+        balance_from_maybe_str(&mut serde_json::Deserializer::from_str(json))
+    };
+    let max = "340282366920938463463374607431768211455";
+    assert_eq!(balance(max)?, u128::MAX);
+    assert_eq!(balance(&format!("\"{max}\""))?, u128::MAX);
+    assert_eq!(
+        balance("\"0xffffffffffffffffffffffffffffffff\"")?,
+        u128::MAX
+    );
+    assert_eq!(balance("\"0x0a\"")?, 10);
+    assert_eq!(balance("18446744073709551616")?, u64::MAX as u128 + 1);
+    // Numbers in json values are kept as they were written
+    let value: serde_json::Value = serde_json::from_str(max)?;
+    assert_eq!(balance_from_maybe_str(value)?, u128::MAX);
+    // Only numbers are maps
+    assert!(balance(r#"{"foo": "123"}"#).is_err());
+    assert!(balance_from_maybe_str(serde_json::json!({ "foo": "123" })).is_err());
+    Ok(())
+}
+
+#[test]
+fn balance_errors_name_field_and_value() {
+    let stake_change = |amount: &str| {
+        serde_json::from_str::<crate::StakeChange>(&format!(
+            r#"{{"timestamp": "2022-09-19T23:26:40", "accumulatedAmount": {amount}}}"#
+        ))
+        .unwrap_err()
+        .to_string()
+    };
+    let too_large = "340282366920938463463374607431768211456";
+    assert!(stake_change(too_large)
+        .starts_with(&format!("accumulatedAmount {too_large} overflows u128")));
+    assert!(stake_change(&format!("\"{too_large}\""))
+        .starts_with(&format!("accumulatedAmount {too_large} overflows u128")));
+    assert!(stake_change("\"0x1ffffffffffffffffffffffffffffffff\"")
+        .starts_with("accumulatedAmount 0x1ffffffffffffffffffffffffffffffff overflows u128"));
+    assert!(stake_change("-1").starts_with("accumulatedAmount -1 is negative"));
+    assert!(
+        stake_change("1.5").starts_with("accumulatedAmount 1.5 is not a whole number of planck")
+    );
+    assert!(stake_change("\"DOT\"")
+        .starts_with("accumulatedAmount DOT is not a whole number of planck"));
+}
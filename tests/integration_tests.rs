@@ -28,42 +28,59 @@ Torbjørn L. <tobben@fastmail.fm>
 Check Polkadot Staking Rewards
 
 USAGE:
-    stake-checker [OPTIONS]
+    stake-checker [OPTIONS] <SUBCOMMAND>
 
 OPTIONS:
-    -a, --account_balances
-            Get account's balances
-
-    -c, --stake_changes
-            Get account's stake changes. Will skip those already listed in known stake changes file
-            listen in .env. Will retrieve at most 100 new stake changes.
-
-    -g, --get_storage <get_storage>...
-            Raw state_getStorage rpc call. Provide at least two args: <method>, and <name>. Third is
-            optional. The program will try to decode the value before printing, but will print raw
-            bytes if the method+name combination is unknown.
-
-    -h, --help
-            Print help information
-
-    -m, --metadata
-            Call endpoint func state_getMetadata
-
-    -p, --properties
-            Call endpoint func system_properties
-
-    -r, --rpc_methods
-            Call endpoint func rpc_methods
-
-    -s, --staking_rewards
-            Get account's staking rewards. Will skip those already listed in known_rewards file
-            listed in .env. Will retrieve at most 100 new rewards.
-
-    -t, --total_issuance
-            Get endpoint chain's total issuance
-
-    -V, --version
-            Print version information
+        --config <file>      Config file to read profiles from. Defaults to stake-checker.toml in
+                             the working directory, or else stake-checker/config.toml in the XDG
+                             config directory. Settings in .env override the profile's.
+    -h, --help               Print help information
+        --output <format>    Print results as a table for people to read, or as json or csv for
+                             other programs. Amounts are given in planck, as strings in json.
+                             [default: table] [possible values: table, json, csv]
+        --profile <name>     Profile of the config file to use. Defaults to its default_profile.
+    -V, --version            Print version information
+
+SUBCOMMANDS:
+    account-balances    Get account's balances
+    chain-rewards       Get account's staking and nomination pool rewards from the events of a
+                            range of blocks, without an indexer. Every block is read, so keep the
+                            range short, and use an archive node as RPC_ENDPOINT.
+    era-apr             Get the network's realised APR of recent eras, from what each era paid
+                            (Staking.ErasValidatorReward) to the stake behind it
+                            (Staking.ErasTotalStake). Commission is not taken out.
+    get-storage         Raw state_getStorage rpc call. Map keys follow the storage name. Keys
+                            are encoded and hashed as described by the runtime metadata. Accounts
+                            can be given as addresses, and any key can be given as 0x-prefixed hex
+                            of its encoding. Values are decoded as described by the runtime metadata
+                            and printed as json. Raw bytes are printed if decoding fails.
+    help                Print this message or the help of the given subcommand(s)
+    metadata            Call endpoint func state_getMetadata
+    nominations         Get the validators that account nominates, with their identity name,
+                            commission, and whether they are active in the active era. Warns when
+                            none of them are.
+    pools               Get account's nomination pool membership: the pool, the account's points
+                            in it, rewards pending a claim, and funds being unbonded
+    properties          Call endpoint func system_properties
+    rpc-methods         Call endpoint func rpc_methods
+    stake-changes       Get account's stake changes. Will skip those already in the store
+                            selected by the profile. Fetches SUBQUERY_PAGE_SIZE (default 100) stake
+                            changes per request, until all new ones are retrieved.
+    staking             Get account's staking ledger: bonded and active funds, funds being
+                            unbonded and when they can be withdrawn, and where rewards are paid. The
+                            ledger is found through the stash's controller.
+    staking-rewards     Get account's staking rewards. Will skip those already in the store
+                            selected by the profile. Fetches SUBQUERY_PAGE_SIZE (default 100)
+                            rewards per request, until all new ones are retrieved.
+    sync                Fetch new stake changes and staking rewards and add them to the store
+                            selected by the profile. Nomination pool rewards are fetched too if
+                            SUBQUERY_ENDPOINT_POOL_REWARDS is set.
+    total-issuance      Get endpoint chain's total issuance
+    watch               Watch account's balances and print every change as it happens. If a file
+                            is given, changes are appended to it as comma separated values instead.
+                            Otherwise changes are also kept in the store selected by the profile: in
+                            the sqlite store, or in KNOWN_BALANCES_FILE if the csv store is given
+                            one. Needs a ws:// or wss:// RPC_ENDPOINT.
 ";
     {
         let mut cmd = Command::cargo_bin("stake-checker")?;
@@ -80,7 +97,7 @@ OPTIONS:
 fn helpful_message_when_envfile_missing() -> Result<(), Box<dyn std::error::Error>> {
     let test_dir = TestDir::new(testfile::generate_name());
     let mut cmd = Command::cargo_bin("stake-checker").unwrap();
-    cmd.arg("--staking_rewards").current_dir(&test_dir.path);
+    cmd.arg("staking-rewards").current_dir(&test_dir.path);
 
     let helpful_message = "Error: Can't find .env file";
     cmd.assert()
@@ -93,7 +110,7 @@ fn helpful_message_when_envfile_missing() -> Result<(), Box<dyn std::error::Erro
 fn helpful_message_when_polkadot_addr_missing() -> Result<(), Box<dyn std::error::Error>> {
     let test_dir = TestDir::new(testfile::generate_name());
     let mut cmd = Command::cargo_bin("stake-checker").unwrap();
-    cmd.arg("--staking_rewards").current_dir(&test_dir.path);
+    cmd.arg("staking-rewards").current_dir(&test_dir.path);
 
     let env_filename = test_dir.path.to_str().unwrap().to_owned() + "/.env";
     let mut env = std::fs::File::create(&env_filename).unwrap();
@@ -123,7 +140,7 @@ fn helpful_message_when_subquery_endpoint_stake_changes_missing(
 ) -> Result<(), Box<dyn std::error::Error>> {
     let test_dir = TestDir::new(testfile::generate_name());
     let mut cmd = Command::cargo_bin("stake-checker").unwrap();
-    cmd.arg("--staking_rewards").current_dir(&test_dir.path);
+    cmd.arg("stake-changes").current_dir(&test_dir.path);
 
     let env_filename = test_dir.path.to_str().unwrap().to_owned() + "/.env";
     let mut env = std::fs::File::create(&env_filename).unwrap();
@@ -140,7 +157,21 @@ fn helpful_message_when_subquery_endpoint_stake_changes_missing(
     .expect("Failed to write to tmp file");
     let _tf = testfile::from_file(&env_filename); // Takes care of deleting tmp file
 
-    let helpful_message = "Error: No SUBQUERY_ENDPOINT_STAKE_CHANGES set in .env";
+    let helpful_message = "Error: stake-changes needs SUBQUERY_ENDPOINT_STAKE_CHANGES set in .env";
+    cmd.assert()
+        .stderr(predicate::str::starts_with(helpful_message));
+
+    Ok(())
+}
+
+#[test]
+fn helpful_message_when_output_format_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+    let test_dir = TestDir::new(testfile::generate_name());
+    let mut cmd = Command::cargo_bin("stake-checker").unwrap();
+    cmd.args(["properties", "--output", "csv"])
+        .current_dir(&test_dir.path);
+
+    let helpful_message = "Error: properties has no csv output";
     cmd.assert()
         .stderr(predicate::str::starts_with(helpful_message));
 
@@ -152,10 +183,10 @@ fn helpful_message_when_subquery_endpoint_stake_changes_missing(
 #[ignore]
 fn total_issued_works_via_real_rpc_node() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("stake-checker")?;
-    cmd.arg("--total_issuance");
+    cmd.arg("total-issuance");
 
     let pred = predicate::str::is_match(
-        "Total issued \\d\\d\\d\\d\\d\\d\\d\\d\\d\\d\\.\\d\\d\\d\\d\\d\\d\\d\\d\\d\\d DOT\n",
+        "Total issued \\d,\\d\\d\\d,\\d\\d\\d,\\d\\d\\d\\.\\d\\d\\d\\d\\d\\d\\d\\d\\d\\d DOT\n",
     )?;
     cmd.assert().stdout(pred);
 